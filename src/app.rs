@@ -14,25 +14,76 @@ pub fn app() -> Result<()> {
     let cli = Cli::parse();
 
     match cli.command {
-        Commands::Load { direct_dirs, recursive_dirs, files } => {
-            load(direct_dirs, recursive_dirs, files)
+        Commands::Load { direct_dirs, recursive_dirs, files, use_archives } => {
+            load(direct_dirs, recursive_dirs, files, use_archives)
         }
 
-        Commands::LoadBy { direct_dirs, recursive_dirs, cache, load_font_list } => {
-            load_by(direct_dirs, recursive_dirs, cache, load_font_list)
-        }
+        Commands::LoadBy {
+            direct_dirs,
+            recursive_dirs,
+            cache,
+            preferred_dirs,
+            load_font_list,
+            encoding,
+            use_archives,
+        } => load_by(
+            direct_dirs, recursive_dirs, cache, preferred_dirs, load_font_list, encoding,
+            use_archives,
+        ),
 
-        Commands::Index { direct_dirs, recursive_dirs, cache, is_absolute, rebuild } => {
-            index(direct_dirs, recursive_dirs, cache, is_absolute, rebuild)
+        Commands::Index {
+            direct_dirs,
+            recursive_dirs,
+            cache,
+            is_absolute,
+            rebuild,
+            use_hash,
+            use_archives,
+        } => {
+            index(direct_dirs, recursive_dirs, cache, is_absolute, rebuild, use_hash, use_archives)
         }
 
         Commands::List {
             direct_dirs,
             recursive_dirs,
             cache,
+            preferred_dirs,
             export_font_list,
             export_fonts_path,
-        } => list(direct_dirs, recursive_dirs, cache, export_font_list, export_fonts_path),
+            encoding,
+            use_archives,
+        } => list(
+            direct_dirs, recursive_dirs, cache, preferred_dirs, export_font_list,
+            export_fonts_path, encoding, use_archives,
+        ),
+
+        Commands::Check { direct_dirs, recursive_dirs, cache, preferred_dirs, encoding } => {
+            check(direct_dirs, recursive_dirs, cache, preferred_dirs, encoding)
+        }
+
+        Commands::Embed {
+            direct_dirs,
+            recursive_dirs,
+            files,
+            cache,
+            preferred_dirs,
+            output_dir,
+            encoding,
+        } => embed(direct_dirs, recursive_dirs, files, cache, preferred_dirs, output_dir, encoding),
+
+        Commands::Mux {
+            direct_dirs,
+            recursive_dirs,
+            files,
+            cache,
+            preferred_dirs,
+            output_dir,
+            encoding,
+        } => mux(direct_dirs, recursive_dirs, files, cache, preferred_dirs, output_dir, encoding),
+
+        Commands::Extract { direct_dirs, recursive_dirs, files, output_dir, encoding } => {
+            extract(direct_dirs, recursive_dirs, files, output_dir, encoding)
+        }
 
         Commands::Clear { cache } => clear(cache),
     }
@@ -41,21 +92,29 @@ pub fn app() -> Result<()> {
 pub fn fontloader_app() -> Result<()> {
     let cli = FontLoaderCli::parse();
     let direct_dirs = if cli.files.is_empty() { vec![PathBuf::from(".")] } else { vec![] };
-    load(direct_dirs, vec![], cli.files)
+    load(direct_dirs, vec![], cli.files, false)
 }
 
 pub fn fontloadersub_app() -> Result<()> {
     let cli = FontLoaderSubCli::parse();
     if !get_cache_path(Some(&PathBuf::from("."))).is_file() {
         eprintln!("Cache not found, building...");
-        index(vec![], vec![PathBuf::from(".")], Some(PathBuf::from(".")), false, false)?;
+        index(
+            vec![],
+            vec![PathBuf::from(".")],
+            Some(PathBuf::from(".")),
+            false,
+            false,
+            false,
+            false,
+        )?;
     }
-    load_by(vec![], cli.dirs, Some(PathBuf::from(".")), false)
+    load_by(vec![], cli.dirs, Some(PathBuf::from(".")), vec![], false, None, false)
 }
 
 pub fn listassfonts_app() -> Result<()> {
     let cli = ListAssFontsCli::parse();
-    list(vec![], cli.dirs, None, false, None)?;
+    list(vec![], cli.dirs, None, vec![], false, None, None, false)?;
     println!("Press enter to exit");
     let _ = std::io::stdin().read_line(&mut String::new());
     Ok(())
@@ -1,10 +1,11 @@
 use std::path::PathBuf;
 
 use clap::{ArgAction, Parser, Subcommand};
+use encoding_rs::Encoding;
 
 // default mode
 
-/// Temporarily install fonts in (A)SSA subtitles
+/// Temporarily install fonts in subtitles
 #[derive(Parser)]
 #[command(version)]
 pub struct Cli {
@@ -24,12 +25,17 @@ pub enum Commands {
         #[arg(short, long = "recurse", value_name = "DIRECTORY")]
         recursive_dirs: Vec<PathBuf>,
 
-        /// Font files
+        /// Font files, or Matroska files to load attached fonts from,
+        /// or ZIP archives with `--zip`
         #[arg(value_name = "FONT_FILE")]
         files: Vec<PathBuf>,
+
+        /// Also scan ZIP archives in the directories
+        #[arg(short = 'z', long = "zip")]
+        use_archives: bool,
     },
 
-    /// Load used fonts in (A)SSA subtitles
+    /// Load used fonts in subtitles
     LoadBy {
         /// Directories to be scanned
         #[arg(short, long = "dir", value_name = "DIRECTORY")]
@@ -43,9 +49,21 @@ pub enum Commands {
         #[arg(short, long)]
         cache: Option<PathBuf>,
 
+        /// Prefer indexed fonts in these directories when multiple files provide a name
+        #[arg(short = 'P', long = "prefer", value_name = "DIRECTORY")]
+        preferred_dirs: Vec<PathBuf>,
+
         /// Load fonts listed in ./fonts.txt
         #[arg(short = 'l', long = "font-list")]
         load_font_list: bool,
+
+        /// Encoding of subtitles without BOM, e.g. gbk, big5 or shift_jis, guessed if not specified
+        #[arg(short, long, value_parser = parse_encoding)]
+        encoding: Option<&'static Encoding>,
+
+        /// Also scan ZIP archives in the directories
+        #[arg(short = 'z', long = "zip")]
+        use_archives: bool,
     },
 
     /// Build index cache
@@ -62,16 +80,24 @@ pub enum Commands {
         #[arg(short, long)]
         cache: Option<PathBuf>,
 
-        /// Avoid translate saved paths to absolute
+        /// Save paths relative to the cache file instead of absolute
         #[arg(short = 'p', long = "portable", action = ArgAction::SetFalse)]
         is_absolute: bool,
 
         /// Clear the cache and rebuild it fresh
         #[arg(short = 'b', long)]
         rebuild: bool,
+
+        /// Compare content hashes to skip files that are only touched
+        #[arg(short = 'H', long = "hash")]
+        use_hash: bool,
+
+        /// Also scan ZIP archives in the directories
+        #[arg(short = 'z', long = "zip")]
+        use_archives: bool,
     },
 
-    /// List used fonts in (A)SSA subtitles
+    /// List used fonts in subtitles
     List {
         /// Directories to be scanned
         #[arg(short, long = "dir", value_name = "DIRECTORY")]
@@ -86,6 +112,10 @@ pub enum Commands {
         #[arg(short, long)]
         cache: Option<Option<PathBuf>>,
 
+        /// Prefer indexed fonts in these directories when multiple files provide a name
+        #[arg(short = 'P', long = "prefer", value_name = "DIRECTORY")]
+        preferred_dirs: Vec<PathBuf>,
+
         /// Export font list to ./fonts.txt
         #[arg(short = 'l', long = "font-list")]
         export_font_list: bool,
@@ -93,6 +123,122 @@ pub enum Commands {
         /// Export installed fonts
         #[arg(short = 'x', long = "export", value_name = "TARGET")]
         export_fonts_path: Option<PathBuf>,
+
+        /// Encoding of subtitles without BOM, e.g. gbk, big5 or shift_jis, guessed if not specified
+        #[arg(short, long, value_parser = parse_encoding)]
+        encoding: Option<&'static Encoding>,
+
+        /// Also scan ZIP archives in the directories
+        #[arg(short = 'z', long = "zip")]
+        use_archives: bool,
+    },
+
+    /// Check if used fonts have glyphs for all the text drawn with them
+    Check {
+        /// Directories to be scanned
+        #[arg(short, long = "dir", value_name = "DIRECTORY")]
+        direct_dirs: Vec<PathBuf>,
+
+        /// Directories to be recursively scanned
+        #[arg(short, long = "recurse", value_name = "DIRECTORY")]
+        recursive_dirs: Vec<PathBuf>,
+
+        /// Manually specify cache file
+        #[arg(short, long)]
+        cache: Option<PathBuf>,
+
+        /// Prefer indexed fonts in these directories when multiple files provide a name
+        #[arg(short = 'P', long = "prefer", value_name = "DIRECTORY")]
+        preferred_dirs: Vec<PathBuf>,
+
+        /// Encoding of subtitles without BOM, e.g. gbk, big5 or shift_jis, guessed if not specified
+        #[arg(short, long, value_parser = parse_encoding)]
+        encoding: Option<&'static Encoding>,
+    },
+
+    /// Embed subsets of used fonts into (A)SSA subtitles
+    Embed {
+        /// Directories to be scanned
+        #[arg(short, long = "dir", value_name = "DIRECTORY")]
+        direct_dirs: Vec<PathBuf>,
+
+        /// Directories to be recursively scanned
+        #[arg(short, long = "recurse", value_name = "DIRECTORY")]
+        recursive_dirs: Vec<PathBuf>,
+
+        /// Subtitle files
+        #[arg(value_name = "SUBTITLE_FILE")]
+        files: Vec<PathBuf>,
+
+        /// Manually specify cache file
+        #[arg(short, long)]
+        cache: Option<PathBuf>,
+
+        /// Prefer indexed fonts in these directories when multiple files provide a name
+        #[arg(short = 'P', long = "prefer", value_name = "DIRECTORY")]
+        preferred_dirs: Vec<PathBuf>,
+
+        /// Write subtitles into this directory instead of next to the originals
+        #[arg(short, long = "output", value_name = "DIRECTORY")]
+        output_dir: Option<PathBuf>,
+
+        /// Encoding of subtitles without BOM, e.g. gbk, big5 or shift_jis, guessed if not specified
+        #[arg(short, long, value_parser = parse_encoding)]
+        encoding: Option<&'static Encoding>,
+    },
+
+    /// Attach used fonts to Matroska files
+    Mux {
+        /// Directories to be scanned
+        #[arg(short, long = "dir", value_name = "DIRECTORY")]
+        direct_dirs: Vec<PathBuf>,
+
+        /// Directories to be recursively scanned
+        #[arg(short, long = "recurse", value_name = "DIRECTORY")]
+        recursive_dirs: Vec<PathBuf>,
+
+        /// Matroska files
+        #[arg(value_name = "VIDEO_FILE")]
+        files: Vec<PathBuf>,
+
+        /// Manually specify cache file
+        #[arg(short, long)]
+        cache: Option<PathBuf>,
+
+        /// Prefer indexed fonts in these directories when multiple files provide a name
+        #[arg(short = 'P', long = "prefer", value_name = "DIRECTORY")]
+        preferred_dirs: Vec<PathBuf>,
+
+        /// Write videos into this directory instead of next to the originals
+        #[arg(short, long = "output", value_name = "DIRECTORY")]
+        output_dir: Option<PathBuf>,
+
+        /// Encoding of subtitles without BOM, e.g. gbk, big5 or shift_jis, guessed if not specified
+        #[arg(short, long, value_parser = parse_encoding)]
+        encoding: Option<&'static Encoding>,
+    },
+
+    /// Extract fonts embedded in (A)SSA subtitles
+    Extract {
+        /// Directories to be scanned
+        #[arg(short, long = "dir", value_name = "DIRECTORY")]
+        direct_dirs: Vec<PathBuf>,
+
+        /// Directories to be recursively scanned
+        #[arg(short, long = "recurse", value_name = "DIRECTORY")]
+        recursive_dirs: Vec<PathBuf>,
+
+        /// Subtitle files
+        #[arg(value_name = "SUBTITLE_FILE")]
+        files: Vec<PathBuf>,
+
+        /// Directory to write fonts into
+        #[arg(short, long = "output", value_name = "DIRECTORY", default_value = ".")]
+        output_dir: PathBuf,
+
+        /// Encoding of subtitles without BOM, e.g. gbk, big5 or shift_jis, guessed if not specified
+        #[arg(short, long, value_parser = parse_encoding)]
+        encoding: Option<&'static Encoding>,
     },
 
     /// Delete font index cache file
@@ -118,7 +264,7 @@ pub struct FontLoaderCli {
 #[derive(Parser)]
 #[command(version)]
 pub struct FontLoaderSubCli {
-    /// Directories containing (A)SSA subtitle files
+    /// Directories containing subtitle files
     #[arg(value_name = "SUBTITLES_DIR")]
     pub dirs: Vec<PathBuf>,
 }
@@ -128,7 +274,13 @@ pub struct FontLoaderSubCli {
 #[derive(Parser)]
 #[command(version)]
 pub struct ListAssFontsCli {
-    /// Directories containing (A)SSA subtitle files
+    /// Directories containing subtitle files
     #[arg(value_name = "SUBTITLES_DIR")]
     pub dirs: Vec<PathBuf>,
 }
+
+/// For `--encoding`, takes labels like `gbk`, `big5`, `shift_jis` and `utf-16le`
+fn parse_encoding(label: &str) -> Result<&'static Encoding, String> {
+    Encoding::for_label(label.trim().as_bytes())
+        .ok_or_else(|| format!("unknown encoding \"{}\"", label))
+}
@@ -1,44 +1,108 @@
-use std::collections::HashSet;
-use std::fs::{copy, remove_file};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs::{canonicalize, copy, create_dir_all, remove_file, write};
 use std::io::{Write, stdin, stdout};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use anyhow::Result;
+use anyhow::{Context, Result, bail};
+use encoding_rs::Encoding;
+use xxhash_rust::xxh3::xxh3_64;
 
-use crate::font::FontProviders;
-use crate::ssa::SsaFonts;
+use crate::archive::{read_member, read_members};
+use crate::font::{
+    FontMatch, FontProviders, FontSource, NameKind, missing_chars, read_font_file, subset_font,
+};
+use crate::matroska::{Attachment, add_attachments, read_attachments};
+use crate::ssa::{DrawnChars, EmbeddedFont, FontStyle, SsaFonts, embed_fonts};
 use crate::system::{Finder, Loader};
 use crate::utils::{
-    get_cache_path, get_cache_path_fallback, get_font_list_path, is_font, walk_dir,
+    get_cache_path, get_cache_path_fallback, get_font_list_path, is_font, is_font_data,
+    is_matroska, is_ssa, is_zip, walk_dir,
 };
 
+// markers of fonts in the output of `list`
+const INSTALLED_INDICATOR: &str = "*";
+const EMBEDDED_INDICATOR: &str = "+";
+const IN_INDEX_INDICATOR: &str = "-";
+const NOT_INSTALLED_INDICATOR: &str = " ";
+
+// lines shown for each character missing in `check`
+const MAX_REPORTED_LINES: usize = 5;
+
 pub fn load(
     direct_dirs: Vec<PathBuf>,
     recursive_dirs: Vec<PathBuf>,
     files: Vec<PathBuf>,
+    use_archives: bool,
 ) -> Result<()> {
     let mut all_files = Vec::new();
+    let may_load =
+        |path: &Path| is_font(path) || is_matroska(path) || (use_archives && is_zip(path));
 
     for dir in direct_dirs {
-        walk_dir(&dir, false, &is_font, &mut |path| all_files.push(path));
+        walk_dir(&dir, false, &may_load, &mut |path| all_files.push(path));
     }
 
     for dir in recursive_dirs {
-        walk_dir(&dir, true, &is_font, &mut |path| all_files.push(path));
+        walk_dir(&dir, true, &may_load, &mut |path| all_files.push(path));
+    }
+
+    all_files.extend(files.into_iter().filter(|file| may_load(file)));
+
+    let (videos, all_files): (Vec<_>, Vec<_>) =
+        all_files.into_iter().partition(|file| is_matroska(file));
+    let (archives, mut all_files): (Vec<_>, Vec<_>) =
+        all_files.into_iter().partition(|file| is_zip(file));
+
+    let mut loader = Loader::new()?;
+
+    // attachments are written to the temporary directory of the loader
+    for video in videos {
+        let attachments = match read_attachments(&video) {
+            Ok(attachments) => attachments,
+            Err(err) => {
+                eprintln!("{:#}", err);
+                continue;
+            }
+        };
+
+        for attachment in attachments.into_iter().filter(|attachment| attachment.is_font()) {
+            let name = safe_file_name(&attachment.name).unwrap_or(Path::new("attachment"));
+            all_files.push(loader.write_temp(name, &attachment.data)?);
+        }
     }
 
-    all_files.extend(files.into_iter().filter(|file| is_font(file)));
+    // so are fonts in archives, other files in them are not extracted
+    for archive in archives {
+        // unlike a broken archive, failing to write a temporary file is fatal
+        let mut is_write_error = false;
+
+        let result = read_members(
+            &archive,
+            |_, head| is_font_data(head),
+            |member| {
+                let name = safe_file_name(&member.name).unwrap_or(Path::new("member"));
+                let path = loader.write_temp(name, &member.data);
+                is_write_error = path.is_err();
+                all_files.push(path?);
+                Ok(())
+            },
+        );
+
+        match result {
+            Err(err) if is_write_error => return Err(err),
+            Err(err) => eprintln!("{:#}", err),
+            Ok(()) => {}
+        }
+    }
 
     if all_files.is_empty() {
         println!("Nothing to load");
         return Ok(());
     }
 
-    let mut loader = Loader::new()?;
+    let loaded = loader.load(&all_files)?;
 
-    loader.load(&all_files)?;
-
-    println!("Loaded {} files", all_files.len());
+    println!("Loaded {} files", loaded);
     wait();
 
     Ok(())
@@ -48,18 +112,24 @@ pub fn load_by(
     direct_dirs: Vec<PathBuf>,
     recursive_dirs: Vec<PathBuf>,
     cache_path: Option<PathBuf>,
+    preferred_dirs: Vec<PathBuf>,
     load_font_list: bool,
+    encoding: Option<&'static Encoding>,
+    use_archives: bool,
 ) -> Result<()> {
-    let cache = FontProviders::load(&get_cache_path_fallback(cache_path.as_deref()))?;
+    let mut cache = FontProviders::load(&get_cache_path_fallback(cache_path.as_deref()))?;
+    cache.prefer_dirs(preferred_dirs);
 
     let mut ssa_fonts = if load_font_list {
-        SsaFonts::load(&get_font_list_path(None)).unwrap_or_else(|_| {
+        SsaFonts::load(&get_font_list_path(None), encoding).unwrap_or_else(|_| {
             eprintln!("Cannot load \"fonts.txt\", ignoring");
             SsaFonts::new()
         })
     } else {
         SsaFonts::new()
     };
+    ssa_fonts.force_encoding(encoding);
+    ssa_fonts.use_archives(use_archives);
 
     for dir in direct_dirs {
         ssa_fonts.index(&dir, false);
@@ -69,7 +139,7 @@ pub fn load_by(
         ssa_fonts.index(&dir, true);
     }
 
-    if ssa_fonts.inner().is_empty() {
+    if ssa_fonts.is_empty() {
         println!("Nothing to load");
         return Ok(());
     }
@@ -77,25 +147,90 @@ pub fn load_by(
     let finder = Finder::new()?;
     let mut loader = Loader::new()?;
 
-    let (names, files): (Vec<_>, HashSet<_>) = ssa_fonts
-        .sorted()
-        .into_iter()
-        .filter(|name| get_installed_file(name, &finder).is_none())
-        .filter_map(|name| {
-            let opt = cache.file_by_font_name(&name);
-            if opt.is_none() {
-                eprintln!("Font \"{}\" missing in index", name);
+    let mut names = Vec::new();
+    let mut files = HashSet::new();
+    // embedded fonts providing multiple names are written once
+    let mut embedded_files: Vec<(&EmbeddedFont, PathBuf)> = Vec::new();
+
+    for ssa_font in ssa_fonts.sorted() {
+        let name = &ssa_font.name;
+
+        if get_installed_file(name, &finder).is_some() {
+            continue;
+        }
+
+        if let Some(font) = ssa_fonts.embedded_font(ssa_font) {
+            let written = embedded_files.iter().find(|(known, _)| std::ptr::eq(*known, font));
+            let path = match written {
+                Some((_, path)) => path.clone(),
+                None => {
+                    let name = safe_file_name(&font.name).unwrap_or(Path::new("embedded"));
+                    let path = loader.write_temp(name, &font.data)?;
+                    embedded_files.push((font, path.clone()));
+                    path
+                }
+            };
+            names.push(format!("{} (embedded)", name));
+            files.insert(FontSource::file(path));
+            continue;
+        }
+
+        // every style needed may come from a different file
+        for style in ssa_font.styles.keys() {
+            let Some(font) = cache.file_by_font_name(name, style.weight, style.is_italic) else {
+                eprintln!("Font \"{}\" ({}) missing in index", name, style);
+                continue;
+            };
+
+            if let Some((min, max)) = font.unreachable_weight {
+                eprintln!(
+                    "Font \"{}\" is variable in weight {}-{}, requested weight {} can't be reached",
+                    name, min, max, style.weight
+                );
             }
-            opt.map(|file| (name, file))
-        })
-        .unzip();
+
+            let mut notes = Vec::new();
+            if *style != FontStyle::REGULAR {
+                notes.push(style.to_string());
+            }
+            if let Some(face_index) = font.face_index {
+                notes.push(format!("face {} in collection", face_index));
+            }
+
+            if notes.is_empty() {
+                names.push(name.clone());
+            } else {
+                names.push(format!("{} ({})", name, notes.join(", ")));
+            }
+            files.insert(font.source);
+        }
+    }
 
     if files.is_empty() {
         println!("Nothing to load");
         return Ok(());
     }
 
-    loader.load(files)?;
+    // only the fonts needed are extracted from archives
+    let mut paths = Vec::new();
+    for FontSource { path, member } in files {
+        let Some(member) = member else {
+            paths.push(path);
+            continue;
+        };
+
+        let data = match read_member(&path, &member) {
+            Ok(data) => data,
+            Err(err) => {
+                eprintln!("{:#}", err);
+                continue;
+            }
+        };
+        let name = safe_file_name(&member).unwrap_or(Path::new("member"));
+        paths.push(loader.write_temp(name, &data)?);
+    }
+
+    loader.load(paths)?;
 
     println!("\nLoaded fonts:\n");
     println!("{}", names.join("\n"));
@@ -110,28 +245,43 @@ pub fn index(
     cache_path: Option<PathBuf>,
     is_absolute: bool,
     rebuild: bool,
+    use_hash: bool,
+    use_archives: bool,
 ) -> Result<()> {
-    let (cache_is_specified, cache_path) =
-        (cache_path.is_some(), get_cache_path(cache_path.as_deref()));
+    let cache_path = get_cache_path(cache_path.as_deref());
 
-    let mut cache = if !rebuild && cache_is_specified && cache_path.is_file() {
+    let mut cache = if !rebuild && cache_path.is_file() {
         println!("Loading cache from \"{}\"", cache_path.display());
         FontProviders::load(&cache_path)?
     } else {
         println!("Creating new cache");
-        FontProviders::new()
+        FontProviders::new(&cache_path)
     };
 
+    let mut parsed = 0;
+    let mut removed = 0;
+
+    // only files in the scanned directories are known to be gone
     for dir in direct_dirs {
-        cache.index(&dir, false);
+        parsed += cache.index(&dir, false, use_hash, use_archives);
+        removed += cache.prune(&dir, false);
     }
 
     for dir in recursive_dirs {
-        cache.index(&dir, true);
+        parsed += cache.index(&dir, true, use_hash, use_archives);
+        removed += cache.prune(&dir, true);
+    }
+
+    println!("Indexed {} new or changed files", parsed);
+
+    if removed > 0 {
+        println!("Removed {} missing files", removed);
     }
 
     if is_absolute {
-        cache.make_absolute()?;
+        cache.make_absolute();
+    } else {
+        cache.make_relative();
     }
 
     cache.save(&cache_path)?;
@@ -140,23 +290,25 @@ pub fn index(
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments, reason = "one per command line option")]
 pub fn list(
     direct_dirs: Vec<PathBuf>,
     recursive_dirs: Vec<PathBuf>,
     cache_path: Option<Option<PathBuf>>,
+    preferred_dirs: Vec<PathBuf>,
     export_font_list: bool,
     export_fonts_path: Option<PathBuf>,
+    encoding: Option<&'static Encoding>,
+    use_archives: bool,
 ) -> Result<()> {
-    const INSTALLED_INDICATOR: &str = "*";
-    const IN_INDEX_INDICATOR: &str = "-";
-    const NOT_INSTALLED_INDICATOR: &str = " ";
-
     #[cfg(target_os = "windows")]
     if export_fonts_path.is_some() {
         unimplemented!("Exporting fonts on Windows is not yet implemeted");
     }
 
     let mut ssa_fonts = SsaFonts::new();
+    ssa_fonts.force_encoding(encoding);
+    ssa_fonts.use_archives(use_archives);
 
     for dir in direct_dirs {
         ssa_fonts.index(&dir, false);
@@ -168,8 +320,12 @@ pub fn list(
 
     let finder = Finder::new()?;
     let cache = match &cache_path {
-        Some(path_opt) => FontProviders::load(&get_cache_path(path_opt.as_deref()))?,
-        None => FontProviders::new(),
+        Some(path_opt) => {
+            let mut cache = FontProviders::load(&get_cache_path(path_opt.as_deref()))?;
+            cache.prefer_dirs(preferred_dirs);
+            Some(cache)
+        }
+        None => None,
     };
 
     let export_fonts_path = export_fonts_path.and_then(|path| {
@@ -181,36 +337,42 @@ pub fn list(
         }
     });
 
-    if cache_path.is_some() {
-        println!(
-            "{} for installed, {} for indexed in cache\n",
-            INSTALLED_INDICATOR, IN_INDEX_INDICATOR
-        );
+    let has_embedded = !ssa_fonts.embedded_fonts().is_empty();
+
+    if cache_path.is_some() || has_embedded {
+        let mut legend = vec![format!("{} for installed", INSTALLED_INDICATOR)];
+        if has_embedded {
+            legend.push(format!("{} for embedded in subtitles or attached", EMBEDDED_INDICATOR));
+        }
+        if cache_path.is_some() {
+            legend.push(format!("{} for indexed in cache", IN_INDEX_INDICATOR));
+        }
+        println!("{}\n", legend.join(", "));
     }
 
-    for name in ssa_fonts.sorted() {
-        let file = if let Some(path) = get_installed_file(&name, &finder) {
+    for ssa_font in ssa_fonts.sorted() {
+        let name = &ssa_font.name;
+
+        let files = if let Some(path) = get_installed_file(name, &finder) {
             println!("[{}] {}", INSTALLED_INDICATOR, name);
-            Some(path)
-        } else if let Some(path) = cache.file_by_font_name(&name) {
-            println!("[{}] {}", IN_INDEX_INDICATOR, name);
-            Some(path.to_owned())
+            vec![FontSource::file(path)]
+        } else if let Some(font) = ssa_fonts.embedded_font(ssa_font) {
+            println!("[{}] {}", EMBEDDED_INDICATOR, name);
+            if let Some(export_path) = &export_fonts_path {
+                write_embedded_font(font, export_path);
+            }
+            vec![]
         } else {
-            println!("[{}] {}", NOT_INSTALLED_INDICATOR, name);
-            None
+            ssa_font
+                .styles
+                .keys()
+                .filter_map(|style| list_style(name, *style, cache.as_ref()))
+                .collect()
         };
 
-        if let Some(export_path) = &export_fonts_path
-            && let Some(file) = file
-        {
-            #[allow(clippy::unwrap_used, reason = "guaranteed valid file name")]
-            let filename = file.file_name().unwrap();
-            if copy(&file, export_path.join(filename)).is_err() {
-                eprintln!(
-                    "Error copying from \"{}\" to \"{}\"",
-                    file.display(),
-                    export_path.display()
-                )
+        if let Some(export_path) = &export_fonts_path {
+            for file in files {
+                export_font(&file, export_path);
             }
         }
     }
@@ -223,6 +385,553 @@ pub fn list(
     Ok(())
 }
 
+// fonts in archives are extracted
+fn export_font(source: &FontSource, dir: &Path) {
+    let Some(filename) = source.name().file_name() else {
+        eprintln!("Invalid font file name \"{}\", ignoring", source);
+        return;
+    };
+    let dst = dir.join(filename);
+
+    let result = match &source.member {
+        Some(member) => read_member(&source.path, member).and_then(|data| {
+            write(&dst, data).with_context(|| format!("Error writing file \"{}\"", dst.display()))
+        }),
+        None => copy(&source.path, &dst)
+            .map(|_| ())
+            .with_context(|| format!("Error copying from \"{}\" to \"{}\"", source, dir.display())),
+    };
+
+    if let Err(err) = result {
+        eprintln!("{:#}", err);
+    }
+}
+
+// print a font not installed in one style, returns the indexed file providing it
+fn list_style(name: &str, style: FontStyle, cache: Option<&FontProviders>) -> Option<FontSource> {
+    let mut notes = Vec::new();
+    if style != FontStyle::REGULAR {
+        notes.push(style.to_string());
+    }
+
+    let print = |indicator: &str, notes: Vec<String>| {
+        if notes.is_empty() {
+            println!("[{}] {}", indicator, name);
+        } else {
+            println!("[{}] {} ({})", indicator, name, notes.join(", "));
+        }
+    };
+
+    let Some(font) =
+        cache.and_then(|cache| cache.file_by_font_name(name, style.weight, style.is_italic))
+    else {
+        print(NOT_INSTALLED_INDICATOR, notes);
+        return None;
+    };
+
+    if font.kind != NameKind::Full {
+        notes.push(format!("matched by {}", font.kind));
+    }
+    if let Some(face_index) = font.face_index {
+        notes.push(format!("face {} in collection", face_index));
+    }
+    if let Some((min, max)) = font.unreachable_weight {
+        notes.push(format!("weight {} out of variable range {}-{}", style.weight, min, max));
+    }
+
+    print(IN_INDEX_INDICATOR, notes);
+
+    if font.candidates.len() > 1 {
+        println!("    ambiguous, {} faces provide this name:", font.candidates.len());
+        for (idx, candidate) in font.candidates.iter().enumerate() {
+            let mark = if idx == 0 { ">" } else { " " };
+            println!("    {} \"{}\" ({})", mark, candidate.source, candidate.details);
+        }
+    }
+
+    Some(font.source)
+}
+
+pub fn check(
+    direct_dirs: Vec<PathBuf>,
+    recursive_dirs: Vec<PathBuf>,
+    cache_path: Option<PathBuf>,
+    preferred_dirs: Vec<PathBuf>,
+    encoding: Option<&'static Encoding>,
+) -> Result<()> {
+    let mut ssa_fonts = SsaFonts::new();
+    ssa_fonts.force_encoding(encoding);
+
+    for dir in direct_dirs {
+        ssa_fonts.index(&dir, false);
+    }
+
+    for dir in recursive_dirs {
+        ssa_fonts.index(&dir, true);
+    }
+
+    if ssa_fonts.is_empty() {
+        println!("Nothing to check");
+        return Ok(());
+    }
+
+    // installed fonts can still be checked without a cache
+    let cache = load_cache_if_present(cache_path, preferred_dirs)?;
+
+    let finder = Finder::new()?;
+    let mut problems = 0;
+
+    for ssa_font in ssa_fonts.sorted() {
+        let name = &ssa_font.name;
+
+        for (style, drawn) in &ssa_font.styles {
+            let label = if *style == FontStyle::REGULAR {
+                format!("\"{}\"", name)
+            } else {
+                format!("\"{}\" ({})", name, style)
+            };
+
+            let Some(font) = find_font_face(name, *style, &finder, cache.as_ref()) else {
+                // provided by every subtitle or video it is drawn in
+                if ssa_fonts.drawn_without_embedded(name, drawn).is_empty() {
+                    continue;
+                }
+
+                println!("Font {} not found\n", label);
+                problems += 1;
+                continue;
+            };
+
+            let chars = drawn.keys().copied().filter(|c| !c.is_control());
+            let face_index = font.face_index.unwrap_or(0);
+            let missing = match missing_chars(&font.source, face_index, chars) {
+                Ok(missing) => missing,
+                Err(err) => {
+                    eprintln!("{:#}", err);
+                    continue;
+                }
+            };
+
+            if missing.is_empty() {
+                continue;
+            }
+
+            problems += 1;
+            println!("Font {} in \"{}\" lacks {} characters:", label, font.source, missing.len());
+            for c in missing {
+                println!("    {}", describe_missing(c, drawn, &ssa_fonts));
+            }
+            println!();
+        }
+    }
+
+    if problems == 0 {
+        println!("All characters are covered by the fonts they are drawn with");
+    }
+
+    Ok(())
+}
+
+pub fn embed(
+    direct_dirs: Vec<PathBuf>,
+    recursive_dirs: Vec<PathBuf>,
+    files: Vec<PathBuf>,
+    cache_path: Option<PathBuf>,
+    preferred_dirs: Vec<PathBuf>,
+    output_dir: Option<PathBuf>,
+    encoding: Option<&'static Encoding>,
+) -> Result<()> {
+    let mut all_files = Vec::new();
+
+    for dir in direct_dirs {
+        walk_dir(&dir, false, &is_ssa, &mut |path| all_files.push(path));
+    }
+
+    for dir in recursive_dirs {
+        walk_dir(&dir, true, &is_ssa, &mut |path| all_files.push(path));
+    }
+
+    all_files.extend(files.into_iter().filter(|file| is_ssa(file)));
+
+    if all_files.is_empty() {
+        println!("Nothing to embed");
+        return Ok(());
+    }
+
+    if let Some(dir) = &output_dir
+        && !dir.is_dir()
+    {
+        bail!("Path is not a directory: \"{}\"", dir.display());
+    }
+
+    let cache = load_cache_if_present(cache_path, preferred_dirs)?;
+    let finder = Finder::new()?;
+
+    for file in all_files {
+        let mut ssa_fonts = SsaFonts::new();
+        ssa_fonts.force_encoding(encoding);
+        ssa_fonts.index_file(file.clone());
+
+        // faces may be shared by multiple names and styles
+        let mut faces: BTreeMap<(FontSource, u32), BTreeSet<char>> = BTreeMap::new();
+
+        for ssa_font in ssa_fonts.sorted() {
+            for (style, drawn) in &ssa_font.styles {
+                // already embedded
+                let drawn = ssa_fonts.drawn_without_embedded(&ssa_font.name, drawn);
+                if drawn.is_empty() {
+                    continue;
+                }
+
+                let Some(font) = find_font_face(&ssa_font.name, *style, &finder, cache.as_ref())
+                else {
+                    eprintln!("Font \"{}\" ({}) not found, not embedded", ssa_font.name, style);
+                    continue;
+                };
+
+                // subsets lose the variation tables, so they'd render in the default instance
+                if let Some(instance) = font.instance {
+                    eprintln!(
+                        "Font \"{}\" ({}) is named instance {} of a variable font, not embedded",
+                        ssa_font.name, style, instance
+                    );
+                    continue;
+                }
+
+                let face = (font.source, font.face_index.unwrap_or(0));
+                faces.entry(face).or_default().extend(drawn.keys().filter(|c| !c.is_control()));
+            }
+        }
+
+        let mut fonts = Vec::new();
+
+        for ((source, face_index), chars) in faces {
+            let (data, ext) = match subset_font(&source, face_index, chars) {
+                Ok(subset) => subset,
+                Err(err) => {
+                    eprintln!("{:#}", err);
+                    continue;
+                }
+            };
+
+            let name = embedded_font_name(source.name(), face_index, ext);
+            fonts.push(EmbeddedFont { name, data });
+        }
+
+        let dst = match &output_dir {
+            #[allow(clippy::unwrap_used, reason = "guaranteed valid file name")]
+            Some(dir) => dir.join(file.file_name().unwrap()),
+            None => suffixed_path(&file, "embedded"),
+        };
+
+        let added = embed_fonts(&file, &dst, &fonts, encoding)?;
+        if added == 0 {
+            println!("No fonts to embed into \"{}\", left unchanged", file.display());
+            continue;
+        }
+
+        println!("Embedded {} fonts into \"{}\"", added, dst.display());
+    }
+
+    Ok(())
+}
+
+pub fn mux(
+    direct_dirs: Vec<PathBuf>,
+    recursive_dirs: Vec<PathBuf>,
+    files: Vec<PathBuf>,
+    cache_path: Option<PathBuf>,
+    preferred_dirs: Vec<PathBuf>,
+    output_dir: Option<PathBuf>,
+    encoding: Option<&'static Encoding>,
+) -> Result<()> {
+    let mut all_files = Vec::new();
+
+    for dir in direct_dirs {
+        walk_dir(&dir, false, &is_matroska, &mut |path| all_files.push(path));
+    }
+
+    for dir in recursive_dirs {
+        walk_dir(&dir, true, &is_matroska, &mut |path| all_files.push(path));
+    }
+
+    all_files.extend(files.into_iter().filter(|file| is_matroska(file)));
+
+    if all_files.is_empty() {
+        println!("Nothing to mux");
+        return Ok(());
+    }
+
+    if let Some(dir) = &output_dir
+        && !dir.is_dir()
+    {
+        bail!("Path is not a directory: \"{}\"", dir.display());
+    }
+
+    let cache = load_cache_if_present(cache_path, preferred_dirs)?;
+    let finder = Finder::new()?;
+
+    for file in all_files {
+        let attached = match read_attachments(&file) {
+            Ok(attached) => attached,
+            Err(err) => {
+                eprintln!("{:#}", err);
+                continue;
+            }
+        };
+
+        let mut ssa_fonts = SsaFonts::new();
+        ssa_fonts.force_encoding(encoding);
+        ssa_fonts.index_file(file.clone());
+
+        // whole files are attached, whichever faces are used
+        let mut font_files = BTreeSet::new();
+
+        for ssa_font in ssa_fonts.sorted() {
+            for (style, drawn) in &ssa_font.styles {
+                // already attached, or embedded in every track it is drawn in
+                if ssa_fonts.drawn_without_embedded(&ssa_font.name, drawn).is_empty() {
+                    continue;
+                }
+
+                let Some(font) = find_font_face(&ssa_font.name, *style, &finder, cache.as_ref())
+                else {
+                    eprintln!("Font \"{}\" ({}) not found, not attached", ssa_font.name, style);
+                    continue;
+                };
+
+                font_files.insert(font.source);
+            }
+        }
+
+        // files attached under other names, or fonts not providing the used names
+        let mut attached_names: HashSet<String> =
+            attached.iter().map(|attachment| attachment.name.to_lowercase()).collect();
+        let mut attached_hashes: HashSet<u64> =
+            attached.iter().map(|attachment| xxh3_64(&attachment.data)).collect();
+
+        let mut attachments = Vec::new();
+
+        for source in font_files {
+            let (data, ext) = match read_font_file(&source) {
+                Ok(font) => font,
+                Err(err) => {
+                    eprintln!("{:#}", err);
+                    continue;
+                }
+            };
+
+            let name = attached_font_name(source.name(), ext);
+            if !attached_names.insert(name.to_lowercase())
+                || !attached_hashes.insert(xxh3_64(&data))
+            {
+                continue;
+            }
+
+            attachments.push(Attachment::font(name, data));
+        }
+
+        if attachments.is_empty() {
+            println!("No fonts to attach to \"{}\"", file.display());
+            continue;
+        }
+
+        let dst = match &output_dir {
+            #[allow(clippy::unwrap_used, reason = "guaranteed valid file name")]
+            Some(dir) => dir.join(file.file_name().unwrap()),
+            None => suffixed_path(&file, "muxed"),
+        };
+
+        // the source is read while the output is written
+        if canonicalize(&dst).is_ok_and(|dst| canonicalize(&file).is_ok_and(|src| src == dst)) {
+            eprintln!("Output \"{}\" is the input itself, skipping", dst.display());
+            continue;
+        }
+
+        if let Err(err) = add_attachments(&file, &dst, &attachments) {
+            eprintln!("{:#}", err);
+            continue;
+        }
+        println!("Attached {} fonts to \"{}\"", attachments.len(), dst.display());
+    }
+
+    Ok(())
+}
+
+pub fn extract(
+    direct_dirs: Vec<PathBuf>,
+    recursive_dirs: Vec<PathBuf>,
+    files: Vec<PathBuf>,
+    output_dir: PathBuf,
+    encoding: Option<&'static Encoding>,
+) -> Result<()> {
+    let mut ssa_fonts = SsaFonts::new();
+    ssa_fonts.force_encoding(encoding);
+
+    for dir in direct_dirs {
+        ssa_fonts.index(&dir, false);
+    }
+
+    for dir in recursive_dirs {
+        ssa_fonts.index(&dir, true);
+    }
+
+    for file in files.into_iter().filter(|file| is_ssa(file)) {
+        ssa_fonts.index_file(file);
+    }
+
+    if ssa_fonts.embedded_fonts().is_empty() {
+        println!("Nothing to extract");
+        return Ok(());
+    }
+
+    create_dir_all(&output_dir)
+        .with_context(|| format!("Error creating directory \"{}\"", output_dir.display()))?;
+
+    let mut extracted = 0;
+    for font in ssa_fonts.embedded_fonts() {
+        if write_embedded_font(font, &output_dir) {
+            extracted += 1;
+        }
+    }
+
+    println!("Extracted {} fonts to \"{}\"", extracted, output_dir.display());
+
+    Ok(())
+}
+
+// existing files are not overwritten, returns whether the font is written
+fn write_embedded_font(font: &EmbeddedFont, dir: &Path) -> bool {
+    let Some(filename) = safe_file_name(&font.name) else {
+        eprintln!("Invalid embedded font name \"{}\", ignoring", font.name);
+        return false;
+    };
+
+    let path = dir.join(filename);
+
+    if path.exists() {
+        eprintln!("File \"{}\" already exists, not overwritten", path.display());
+        return false;
+    }
+
+    if write(&path, &font.data).is_err() {
+        eprintln!("Error writing file \"{}\"", path.display());
+        return false;
+    }
+
+    true
+}
+
+// names come from the subtitles or videos, don't let them escape the directory
+fn safe_file_name(name: &str) -> Option<&Path> {
+    Path::new(name).file_name().map(Path::new)
+}
+
+// decoded web fonts get the extension of their format
+fn attached_font_name(path: &Path, ext: &str) -> String {
+    format!("{}.{}", font_file_stem(path), ext)
+}
+
+fn font_file_stem(path: &Path) -> String {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    // web fonts are often named like `Foo.ttf.woff2`
+    let stem = stem.strip_suffix(".ttf").or_else(|| stem.strip_suffix(".otf")).unwrap_or(&stem);
+    stem.to_owned()
+}
+
+// same as Aegisub, `_0` stands for the encoding in the original SSA convention
+fn embedded_font_name(path: &Path, face_index: u32, ext: &str) -> String {
+    let stem = font_file_stem(path);
+
+    if face_index == 0 {
+        format!("{}_0.{}", stem, ext)
+    } else {
+        format!("{}-{}_0.{}", stem, face_index, ext)
+    }
+}
+
+// `suffix` is put before the extension, e.g. `foo.embedded.ass` for `foo.ass`
+fn suffixed_path(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = path.extension().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{}.{}.{}", stem, suffix, ext))
+}
+
+// installed fonts first, with the face in the file picked the same way as from the cache
+fn find_font_face(
+    name: &str,
+    style: FontStyle,
+    finder: &Finder,
+    cache: Option<&FontProviders>,
+) -> Option<FontMatch> {
+    let installed = finder
+        .get_styled_font_file(name, style.weight, style.is_italic)
+        .unwrap_or_else(|_| {
+            eprintln!(
+                "Error checking installation state of \"{}\", treating as not installed",
+                name
+            );
+            None
+        })
+        .filter(|path| path.is_file())
+        .and_then(|path| FontProviders::of_file(&path))
+        .and_then(|file| file.file_by_font_name(name, style.weight, style.is_italic));
+
+    installed.or_else(|| cache?.file_by_font_name(name, style.weight, style.is_italic))
+}
+
+fn load_cache_if_present(
+    cache_path: Option<PathBuf>,
+    preferred_dirs: Vec<PathBuf>,
+) -> Result<Option<FontProviders>> {
+    let is_explicit = cache_path.is_some();
+    let cache_path = get_cache_path_fallback(cache_path.as_deref());
+
+    // only the default cache may be absent
+    if !cache_path.is_file() {
+        if is_explicit {
+            bail!("Cache \"{}\" not found", cache_path.display());
+        }
+        return Ok(None);
+    }
+
+    let mut cache = FontProviders::load(&cache_path)?;
+    cache.prefer_dirs(preferred_dirs);
+
+    Ok(Some(cache))
+}
+
+// the character and where it is drawn, e.g. `U+6F22 漢 "a.ass" line 3, 5`
+fn describe_missing(c: char, drawn: &DrawnChars, ssa_fonts: &SsaFonts) -> String {
+    let shown = if c.is_whitespace() { String::new() } else { format!(" {}", c) };
+    let mut text = format!("U+{:04X}{}", u32::from(c), shown);
+
+    let locations = drawn.get(&c).map(Vec::as_slice).unwrap_or_default();
+
+    let mut files: Vec<(usize, Vec<usize>)> = Vec::new();
+    for location in locations.iter().take(MAX_REPORTED_LINES) {
+        match files.last_mut() {
+            Some((file, lines)) if *file == location.file => lines.push(location.line),
+            _ => files.push((location.file, vec![location.line])),
+        }
+    }
+
+    for (file, lines) in files {
+        let lines: Vec<_> = lines.iter().map(usize::to_string).collect();
+        text.push_str(&format!(
+            " \"{}\" line {}",
+            ssa_fonts.file(file).display(),
+            lines.join(", ")
+        ));
+    }
+
+    if locations.len() > MAX_REPORTED_LINES {
+        text.push_str(&format!(" and {} more", locations.len() - MAX_REPORTED_LINES));
+    }
+
+    text
+}
+
 pub fn clear(cache_path: Option<PathBuf>) -> Result<()> {
     let cache_path = get_cache_path(cache_path.as_deref());
 
@@ -0,0 +1,132 @@
+// ZIP archives, as font packs and subtitle packs are usually distributed,
+// members are read into memory one at a time, nothing is unpacked to disk here
+
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use zip::ZipArchive;
+use zip::read::ZipFile;
+
+// enough for signatures of fonts
+const HEAD_LEN: u64 = 4;
+
+// far larger than any font or subtitle, members are held in memory
+const MAX_MEMBER_SIZE: u64 = 256 << 20;
+
+/// A file in an archive
+pub struct Member {
+    /// Sanitized name in the archive, e.g. `fonts/Foo.ttf`
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+/// Calls `process` with each file in the archive that `may_read` accepts,
+/// it is given the name of each file and its first few bytes
+pub fn read_members(
+    path: &Path,
+    may_read: impl Fn(&str, &[u8]) -> bool,
+    mut process: impl FnMut(Member) -> Result<()>,
+) -> Result<()> {
+    let mut archive = open(path)?;
+
+    for idx in 0..archive.len() {
+        // e.g. encrypted, or compressed by an unsupported method
+        let mut file = match archive.by_index(idx) {
+            Ok(file) => file,
+            Err(err) => {
+                eprintln!(
+                    "Error reading member {} of \"{}\" ({}), ignoring",
+                    idx,
+                    path.display(),
+                    err
+                );
+                continue;
+            }
+        };
+
+        // escaping the archive with `..`
+        let Some(name) = enclosed_name(&file).filter(|_| file.is_file()) else {
+            continue;
+        };
+
+        if file.size() > MAX_MEMBER_SIZE {
+            eprintln!("\"{}\" is too large, ignoring", member_path(path, &name).display());
+            continue;
+        }
+
+        let mut data = Vec::new();
+
+        let result = (&mut file).take(HEAD_LEN).read_to_end(&mut data).and_then(|_| {
+            if may_read(&name, &data) {
+                read_rest(&mut file, &mut data).map(Some)
+            } else {
+                Ok(None)
+            }
+        });
+
+        match result {
+            Ok(Some(_)) => process(Member { name, data })?,
+            Ok(None) => {}
+            Err(err) => eprintln!(
+                "Error reading \"{}\" ({}), ignoring",
+                member_path(path, &name).display(),
+                err
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+pub fn read_member(path: &Path, name: &str) -> Result<Vec<u8>> {
+    let mut archive = open(path)?;
+    let member = member_path(path, name);
+
+    // names are sanitized, so they are matched the same way
+    let idx = (0..archive.len())
+        .find(|&idx| {
+            archive
+                .by_index_raw(idx)
+                .is_ok_and(|file| enclosed_name(&file).as_deref() == Some(name))
+        })
+        .with_context(|| format!("File \"{}\" not found", member.display()))?;
+
+    let mut file = archive
+        .by_index(idx)
+        .with_context(|| format!("Error reading file \"{}\"", member.display()))?;
+
+    let mut data = Vec::new();
+    read_rest(&mut file, &mut data)
+        .with_context(|| format!("Error reading file \"{}\"", member.display()))?;
+
+    Ok(data)
+}
+
+// the declared size is not trusted, the data may be longer
+fn read_rest(file: &mut impl Read, data: &mut Vec<u8>) -> io::Result<()> {
+    let limit = MAX_MEMBER_SIZE - data.len() as u64;
+    if file.take(limit + 1).read_to_end(data)? as u64 > limit {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "file too large"));
+    }
+    Ok(())
+}
+
+// leading `/` and `.` are dropped, `None` if it goes outside the archive
+fn enclosed_name<R: Read>(file: &ZipFile<'_, R>) -> Option<String> {
+    file.enclosed_name().map(|name| name.to_string_lossy().into_owned())
+}
+
+/// How a member is shown, as if the archive were a directory
+pub fn member_path(path: &Path, name: &str) -> PathBuf {
+    path.join(name)
+}
+
+fn open(path: &Path) -> Result<ZipArchive<BufReader<File>>> {
+    let file =
+        File::open(path).with_context(|| format!("Error opening file \"{}\"", path.display()))?;
+
+    ZipArchive::new(BufReader::new(file))
+        .with_context(|| format!("Error reading archive \"{}\"", path.display()))
+}
@@ -1,40 +1,328 @@
-use std::collections::HashMap;
-use std::fs::{File, create_dir_all};
+mod cache;
+mod encoding;
+mod variation;
+mod woff;
+
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::fs::{File, create_dir_all, metadata};
+use std::io::{BufReader, BufWriter, Write};
 use std::path::{Path, PathBuf, absolute};
+use std::time::SystemTime;
 
-use anyhow::{Context, Result};
-use bincode::config::standard;
-use bincode::{Decode, Encode, decode_from_std_read, encode_into_std_write};
+use anyhow::{Context, Result, bail};
+use bincode::{Decode, Encode};
 use memmap2::Mmap;
-use ttf_parser::name_id::FULL_NAME;
-use ttf_parser::{Face, fonts_in_collection};
+use ttf_parser::name_id::{FAMILY, FULL_NAME, POST_SCRIPT_NAME, TYPOGRAPHIC_FAMILY};
+use ttf_parser::{Face, Tag, fonts_in_collection};
+use xxhash_rust::xxh3::xxh3_64;
 
-use crate::utils::{is_font, parse_style, walk_dir};
+use self::cache::Contents;
+use self::encoding::decode_name;
+use self::variation::{named_instances, weight_range};
+use self::woff::{WOFF_SIGNATURE, decode_web_font};
+use crate::archive::{member_path, read_member, read_members};
+use crate::utils::{
+    is_font, is_font_data, is_zip, normalize_font_name, normalize_path, relative_path, walk_dir,
+};
 
+// `path` must stay the first field, see `cache`
 #[derive(Encode, Decode)]
 struct FontFile {
     path: PathBuf,
-    names: Vec<String>,
-    is_variable: bool,
+    /// Name of the font in the ZIP archive at `path`,
+    /// `stamp` and `hash` are then of the archive
+    member: Option<String>,
+    stamp: FileStamp,
+    hash: Option<u64>,
+    faces: Vec<FontFace>,
+    is_collection: bool,
+    revision: u32,
+    format: FontFormat,
+    container: FontContainer,
 }
 
 #[derive(Encode, Decode)]
+struct FontFace {
+    /// Index of the face in a collection, 0 for a regular font file
+    index: u32,
+    /// Index of the named instance in `fvar` if the face stands for one
+    instance: Option<u16>,
+    names: Vec<FontName>,
+    is_variable: bool,
+    /// Range of the `wght` axis of a variable font
+    weight_range: Option<(u16, u16)>,
+    /// `usWeightClass` in `OS/2` table
+    weight: u16,
+    /// `usWidthClass` in `OS/2` table, from 1 (ultra-condensed) to 9 (ultra-expanded)
+    width: u16,
+    is_italic: bool,
+    is_oblique: bool,
+}
+
+impl FontFace {
+    /// Same as libass, the lower the more similar, 0 for exactly the requested style
+    fn dissimilarity(&self, weight: u16, is_italic: bool) -> u32 {
+        const SLANT_NONE: u32 = 0;
+        const SLANT_ITALIC: u32 = 100;
+        const SLANT_OBLIQUE: u32 = 110;
+        // libass always requests normal width
+        const WIDTH_NORMAL: u32 = 100;
+
+        let slant = if self.is_italic {
+            SLANT_ITALIC
+        } else if self.is_oblique {
+            SLANT_OBLIQUE
+        } else {
+            SLANT_NONE
+        };
+
+        // percentages, in the way CSS and Fontconfig interpret width classes
+        let width: u32 = match self.width {
+            1 => 50,
+            2 => 62,
+            3 => 75,
+            4 => 87,
+            6 => 112,
+            7 => 125,
+            8 => 150,
+            9 => 200,
+            _ => 100,
+        };
+
+        u32::from(self.weight).abs_diff(u32::from(weight))
+            + slant.abs_diff(if is_italic { SLANT_ITALIC } else { SLANT_NONE })
+            + width.abs_diff(WIDTH_NORMAL)
+    }
+
+    fn details(&self) -> String {
+        let mut details = format!("weight {}", self.weight);
+
+        if self.is_italic {
+            details.push_str(", italic");
+        } else if self.is_oblique {
+            details.push_str(", oblique");
+        }
+
+        if self.width != 5 {
+            details.push_str(&format!(", width class {}", self.width));
+        }
+
+        if let Some(instance) = self.instance {
+            details.push_str(&format!(", named instance {}", instance));
+        } else if self.is_variable {
+            details.push_str(", variable");
+        }
+
+        details
+    }
+}
+
+#[derive(Encode, Decode, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct FontName {
+    name: String,
+    kind: NameKind,
+}
+
+/// Kinds of names a renderer would match `\fn` against,
+/// ordered from the least to the most specific
+#[derive(Encode, Decode, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum NameKind {
+    TypographicFamily,
+    Family,
+    PostScript,
+    Full,
+}
+
+impl NameKind {
+    fn from_name_id(name_id: u16) -> Option<Self> {
+        match name_id {
+            TYPOGRAPHIC_FAMILY => Some(Self::TypographicFamily),
+            FAMILY => Some(Self::Family),
+            POST_SCRIPT_NAME => Some(Self::PostScript),
+            FULL_NAME => Some(Self::Full),
+            _ => None,
+        }
+    }
+}
+
+impl Display for NameKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::TypographicFamily => "typographic family name",
+            Self::Family => "family name",
+            Self::PostScript => "PostScript name",
+            Self::Full => "full name",
+        })
+    }
+}
+
+#[derive(Encode, Decode, Clone, Copy)]
+enum FontFormat {
+    TrueType,
+    Cff,
+}
+
+/// How the sfnt data is stored in the file
+#[derive(Encode, Decode, Clone, Copy)]
+enum FontContainer {
+    Sfnt,
+    Woff,
+    Woff2,
+}
+
+#[derive(Encode, Decode, Clone, Copy, PartialEq)]
+struct FileStamp {
+    size: u64,
+    modified: SystemTime,
+}
+
+impl FileStamp {
+    fn of(path: &Path) -> Option<Self> {
+        let meta = metadata(path).ok()?;
+        Some(Self { size: meta.len(), modified: meta.modified().ok()? })
+    }
+}
+
 pub struct FontProviders {
     files: Vec<FontFile>,
-    map: HashMap<String, usize>,
+    /// All faces providing each name, keyed by normalized names
+    map: HashMap<String, Vec<NameEntry>>,
+    /// Absolute path of the directory containing the cache file,
+    /// relative paths in `files` are relative to it
+    root: PathBuf,
+    /// Files in these directories win over newer versions elsewhere
+    preferred_dirs: Vec<PathBuf>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct NameEntry {
+    file: usize,
+    face: usize,
+    kind: NameKind,
+}
+
+pub const WEIGHT_REGULAR: u16 = 400;
+pub const WEIGHT_BOLD: u16 = 700;
+
+/// A font file, or a font in a ZIP archive
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FontSource {
+    pub path: PathBuf,
+    /// Name of the font in the archive at `path`
+    pub member: Option<String>,
+}
+
+impl FontSource {
+    pub fn file(path: PathBuf) -> Self {
+        Self { path, member: None }
+    }
+
+    /// The font file itself, the member for fonts in archives
+    pub fn name(&self) -> &Path {
+        self.member.as_deref().map_or(&self.path, Path::new)
+    }
+}
+
+impl Display for FontSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.member {
+            Some(member) => write!(f, "{}", member_path(&self.path, member).display()),
+            None => write!(f, "{}", self.path.display()),
+        }
+    }
+}
+
+pub struct FontMatch {
+    pub source: FontSource,
+    /// Index of the picked face if the file is a collection
+    pub face_index: Option<u32>,
+    /// Index of the named instance in `fvar` if the picked face stands for one
+    pub instance: Option<u16>,
+    /// How the name is matched
+    pub kind: NameKind,
+    /// Range of the weight axis of the picked variable font,
+    /// if it can't reach the requested weight
+    pub unreachable_weight: Option<(u16, u16)>,
+    /// All equally good faces, the picked one first
+    pub candidates: Vec<FontCandidate>,
+}
+
+pub struct FontCandidate {
+    pub source: FontSource,
+    /// Version, format, style, etc.
+    pub details: String,
 }
 
 impl FontProviders {
-    pub fn new() -> Self {
-        Self { files: Vec::new(), map: HashMap::new() }
+    /// `path` is where the cache file is going to be saved
+    pub fn new(path: &Path) -> Self {
+        let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let root = absolute(dir).map(|dir| normalize_path(&dir)).unwrap_or_else(|_| dir.to_owned());
+        Self { files: Vec::new(), map: HashMap::new(), root, preferred_dirs: Vec::new() }
+    }
+
+    /// Faces of a single font file, e.g. an installed one, to pick one of them
+    /// the same way as from the cache, not meant to be saved
+    pub fn of_file(path: &Path) -> Option<Self> {
+        let stamp = FileStamp::of(path)?;
+        let path = absolute(path).map(|path| normalize_path(&path)).ok()?;
+
+        let mut providers = Self::new(&path);
+        providers.files.push(FontFile::read(path, stamp, false, None));
+        providers.rebuild_map();
+
+        Some(providers)
     }
 
     pub fn load(path: &Path) -> Result<Self> {
-        let mut file = File::open(path)
+        let file = File::open(path)
             .with_context(|| format!("Error opening file \"{}\"", path.display()))?;
 
-        decode_from_std_read(&mut file, standard())
-            .with_context(|| format!("Error reading file \"{}\"", path.display()))
+        let contents = cache::read(&mut BufReader::new(file))
+            .with_context(|| format!("Error reading file \"{}\"", path.display()))?;
+
+        let mut cache = Self::new(path);
+
+        match contents {
+            Contents::Current(files) => cache.files = files,
+
+            Contents::Outdated { generator, mut paths } => {
+                // fonts in the same archive are stored next to each other
+                paths.dedup();
+
+                eprintln!(
+                    "Cache \"{}\" was built by fntldr {}, migrating by reading {} font files again",
+                    path.display(),
+                    generator,
+                    paths.len()
+                );
+                eprintln!("Run `fntldr index` to save the migrated cache");
+
+                for path in paths {
+                    let path = cache.resolve(&path);
+                    match FileStamp::of(&path) {
+                        Some(stamp) if is_zip(&path) => {
+                            cache.files.extend(FontFile::read_archive(path, stamp, false, None))
+                        }
+                        Some(stamp) => cache.files.push(FontFile::read(path, stamp, false, None)),
+                        None => eprintln!("Error reading file \"{}\", dropping", path.display()),
+                    }
+                }
+            }
+
+            Contents::Newer { generator } => bail!(
+                "Cache \"{}\" was built by a newer fntldr {}, please upgrade or run `fntldr index \
+                 --rebuild`",
+                path.display(),
+                generator
+            ),
+        }
+
+        cache.rebuild_map();
+
+        Ok(cache)
     }
 
     pub fn save(&self, path: &Path) -> Result<()> {
@@ -43,90 +331,595 @@ impl FontProviders {
                 .with_context(|| format!("Error creating directory \"{}\"", path.display()))?;
         }
 
-        let mut file = File::options()
+        let file = File::options()
             .write(true)
             .create(true)
             .truncate(true)
             .open(path)
             .with_context(|| format!("Error opening file \"{}\"", path.display()))?;
 
-        encode_into_std_write(self, &mut file, standard())
+        let mut writer = BufWriter::new(file);
+
+        cache::write(&mut writer, &self.files)
             .with_context(|| format!("Error writing file \"{}\"", path.display()))?;
 
+        writer.flush().with_context(|| format!("Error writing file \"{}\"", path.display()))?;
+
         Ok(())
     }
 
-    pub fn index(&mut self, path: &Path, is_recursive: bool) {
+    /// Returns the number of files that were (re)parsed,
+    /// fonts in ZIP archives are indexed too if `use_archives` is set
+    pub fn index(
+        &mut self,
+        path: &Path,
+        is_recursive: bool,
+        use_hash: bool,
+        use_archives: bool,
+    ) -> usize {
+        // entries of each file on disk, archives may have many
+        let mut known: HashMap<PathBuf, Vec<usize>> = HashMap::new();
+        for (idx, file) in self.files.iter().enumerate() {
+            known.entry(self.resolve(&file.path)).or_default().push(idx);
+        }
+
+        // entries of changed archives, replaced by ones read again
+        let mut stale = HashSet::new();
+        let mut parsed = 0;
+
         let mut process = |path: PathBuf| {
-            let (names, is_variable) = get_font_names(&path);
-            let idx = self.files.len();
-            self.map.extend(names.iter().cloned().map(|name| (name, idx)));
-            self.files.push(FontFile { path, names, is_variable });
+            let Some(stamp) = FileStamp::of(&path) else {
+                eprintln!("Error reading file \"{}\"", path.display());
+                return;
+            };
+
+            let Ok(path) = absolute(&path).map(|path| normalize_path(&path)) else {
+                eprintln!("Error resolving path \"{}\"", path.display());
+                return;
+            };
+
+            let is_archive = is_zip(&path);
+
+            let Some(idxs) = known.get(&path).cloned() else {
+                let read = if is_archive {
+                    FontFile::read_archive(path.clone(), stamp, use_hash, None)
+                } else {
+                    vec![FontFile::read(path.clone(), stamp, use_hash, None)]
+                };
+                parsed += read.len();
+                known.insert(path, (self.files.len()..self.files.len() + read.len()).collect());
+                self.files.extend(read);
+                return;
+            };
+
+            let file = &self.files[idxs[0]];
+
+            if file.stamp == stamp {
+                return;
+            }
+
+            // hashed only once, reused for the new entry if the content did change
+            let hash = if use_hash && file.stamp.size == stamp.size && file.hash.is_some() {
+                hash_file(&path)
+            } else {
+                None
+            };
+
+            // only touched, content is the same
+            if hash.is_some() && hash == file.hash {
+                for idx in idxs {
+                    self.files[idx].stamp = stamp;
+                }
+                return;
+            }
+
+            if !is_archive {
+                self.files[idxs[0]] = FontFile::read(path, stamp, use_hash, hash);
+                parsed += 1;
+                return;
+            }
+
+            // fonts may be added to or removed from the archive
+            let read = FontFile::read_archive(path.clone(), stamp, use_hash, hash);
+            stale.extend(idxs);
+            parsed += read.len();
+            known.insert(path, (self.files.len()..self.files.len() + read.len()).collect());
+            self.files.extend(read);
         };
 
-        walk_dir(path, is_recursive, &is_font, &mut process);
+        let may_index = |path: &Path| is_font(path) || (use_archives && is_zip(path));
+        walk_dir(path, is_recursive, &may_index, &mut process);
+
+        if !stale.is_empty() {
+            let mut idx = 0;
+            self.files.retain(|_| {
+                let keep = !stale.contains(&idx);
+                idx += 1;
+                keep
+            });
+        }
+
+        self.rebuild_map();
+
+        parsed
     }
 
-    pub fn make_absolute(&mut self) -> Result<()> {
+    /// Drop entries in the directory whose files no longer exist, entries elsewhere
+    /// are kept, as they may be on drives not mounted now, returns the number of removed entries
+    pub fn prune(&mut self, dir: &Path, is_recursive: bool) -> usize {
+        // an unmounted drive looks the same as deleted files
+        if !dir.is_dir() {
+            return 0;
+        }
+
+        let Ok(dir) = absolute(dir).map(|dir| normalize_path(&dir)) else {
+            return 0;
+        };
+
+        let len = self.files.len();
+        self.files.retain(|file| {
+            let path = normalize_path(&self.root.join(&file.path));
+            let is_in_dir =
+                if is_recursive { path.starts_with(&dir) } else { path.parent() == Some(&dir) };
+            !is_in_dir || path.is_file()
+        });
+        self.rebuild_map();
+        len - self.files.len()
+    }
+
+    pub fn make_absolute(&mut self) {
         for file in &mut self.files {
-            file.path = absolute(&file.path)?;
+            file.path = normalize_path(&self.root.join(&file.path));
         }
+    }
 
-        Ok(())
+    /// Make paths relative to the cache file, so it can be moved together with the fonts,
+    /// paths that can't be expressed this way (e.g. on another drive) are left absolute
+    pub fn make_relative(&mut self) {
+        for file in &mut self.files {
+            let path = normalize_path(&self.root.join(&file.path));
+            file.path = relative_path(&path, &self.root).unwrap_or(path);
+        }
+    }
+
+    pub fn prefer_dirs(&mut self, dirs: impl IntoIterator<Item = impl AsRef<Path>>) {
+        self.preferred_dirs = dirs
+            .into_iter()
+            .filter_map(|dir| absolute(dir.as_ref()).ok())
+            .map(|dir| normalize_path(&dir))
+            .collect();
+    }
+
+    /// Find the face closest to the requested style, like libass does:
+    /// exact full names and PostScript names always win, otherwise
+    /// the most similar face with the family name is picked
+    pub fn file_by_font_name(&self, name: &str, weight: u16, is_italic: bool) -> Option<FontMatch> {
+        let name = normalize_font_name(name);
+        let entries = self.map.get(&name)?;
+
+        // typographic family names are only tried when nothing else matches
+        let mut entries: Vec<_> =
+            entries.iter().filter(|entry| entry.kind != NameKind::TypographicFamily).collect();
+        if entries.is_empty() {
+            entries = self.map.get(&name)?.iter().collect();
+        }
+
+        let rank = |entry: &NameEntry| {
+            let score = match entry.kind {
+                NameKind::Full | NameKind::PostScript => 0,
+                NameKind::Family | NameKind::TypographicFamily => {
+                    self.files[entry.file].faces[entry.face].dissimilarity(weight, is_italic)
+                }
+            };
+            (score, Reverse(entry.kind))
+        };
+
+        let best_rank = entries.iter().map(|entry| rank(entry)).min()?;
+        let candidates = entries.into_iter().filter(|entry| rank(entry) == best_rank);
+
+        self.pick(candidates, best_rank.1.0, weight)
+    }
+
+    /// Prefer files in preferred directories, then newer font revisions,
+    /// then the file indexed the latest, then the first face in it
+    fn pick<'a>(
+        &self,
+        candidates: impl Iterator<Item = &'a NameEntry>,
+        kind: NameKind,
+        weight: u16,
+    ) -> Option<FontMatch> {
+        let mut candidates: Vec<_> = candidates.collect();
+
+        let (best_idx, _) = candidates.iter().enumerate().max_by_key(|(_, entry)| {
+            let file = &self.files[entry.file];
+            let path = self.resolve(&file.path);
+            let is_preferred = self.preferred_dirs.iter().any(|dir| path.starts_with(dir));
+            (is_preferred, file.revision, entry.file, Reverse(entry.face))
+        })?;
+
+        let best = candidates.remove(best_idx);
+        candidates.insert(0, best);
+
+        let best_file = &self.files[best.file];
+        let best_face = &best_file.faces[best.face];
+
+        // exact names already imply the style
+        let unreachable_weight = match kind {
+            NameKind::Family | NameKind::TypographicFamily => {
+                best_face.weight_range.filter(|&(min, max)| weight < min || weight > max)
+            }
+            NameKind::PostScript | NameKind::Full => None,
+        };
+
+        Some(FontMatch {
+            source: self.source(best_file),
+            face_index: best_file.is_collection.then_some(best_face.index),
+            instance: best_face.instance,
+            kind,
+            unreachable_weight,
+            candidates: candidates
+                .into_iter()
+                .map(|entry| {
+                    let file = &self.files[entry.file];
+                    FontCandidate { source: self.source(file), details: file.details(entry.face) }
+                })
+                .collect(),
+        })
+    }
+
+    fn resolve(&self, path: &Path) -> PathBuf {
+        normalize_path(&self.root.join(path))
+    }
+
+    fn source(&self, file: &FontFile) -> FontSource {
+        FontSource { path: self.resolve(&file.path), member: file.member.clone() }
     }
 
-    pub fn file_by_font_name(&self, name: &str) -> Option<&PathBuf> {
-        if let Some(&file_idx) = self.map.get(name) {
-            return Some(&self.files[file_idx].path);
+    fn rebuild_map(&mut self) {
+        self.map.clear();
+        for (file_idx, file) in self.files.iter().enumerate() {
+            for (face_idx, face) in file.faces.iter().enumerate() {
+                for FontName { name, kind } in &face.names {
+                    let entry = NameEntry { file: file_idx, face: face_idx, kind: *kind };
+                    let entries = self.map.entry(normalize_font_name(name)).or_default();
+                    // names differing only in case or spacing
+                    if !entries.contains(&entry) {
+                        entries.push(entry);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl FontFile {
+    fn new(path: PathBuf, member: Option<String>, stamp: FileStamp) -> Self {
+        Self {
+            path,
+            member,
+            stamp,
+            hash: None,
+            faces: Vec::new(),
+            is_collection: false,
+            revision: 0,
+            format: FontFormat::TrueType,
+            container: FontContainer::Sfnt,
+        }
+    }
+
+    /// `known_hash` is taken instead of hashing the file again
+    fn read(path: PathBuf, stamp: FileStamp, use_hash: bool, known_hash: Option<u64>) -> Self {
+        let Some(mapped) = map_file(&path) else {
+            eprintln!("Error reading file \"{}\"", path.display());
+            return Self::new(path, None, stamp);
+        };
+
+        let hash = known_hash.or_else(|| use_hash.then(|| xxh3_64(&mapped)));
+
+        Self::from_data(path, None, stamp, hash, &mapped)
+    }
+
+    /// Every font in the archive, other files are skipped,
+    /// an archive without fonts gets a single entry without faces
+    /// so that it isn't read again until it changes
+    fn read_archive(
+        path: PathBuf,
+        stamp: FileStamp,
+        use_hash: bool,
+        known_hash: Option<u64>,
+    ) -> Vec<Self> {
+        let hash = known_hash.or_else(|| if use_hash { hash_file(&path) } else { None });
+        let mut files = Vec::new();
+
+        let result = read_members(
+            &path,
+            |_, head| is_font_data(head),
+            |member| {
+                files.push(Self::from_data(
+                    path.clone(),
+                    Some(member.name),
+                    stamp,
+                    hash,
+                    &member.data,
+                ));
+                Ok(())
+            },
+        );
+
+        if let Err(err) = result {
+            eprintln!("{:#}", err);
+        }
+
+        if files.is_empty() {
+            let mut file = Self::new(path, None, stamp);
+            file.hash = hash;
+            files.push(file);
         }
 
-        // fallback:
-        // match variable fonts, only by family name
-        // assuming variable fonts provide any weight
-        let (family, _) = parse_style(name);
-        if let Some(&file_idx) = self.map.get(family) {
-            let file = &self.files[file_idx];
-            if file.is_variable {
-                return Some(&file.path);
+        files
+    }
+
+    fn from_data(
+        path: PathBuf,
+        member: Option<String>,
+        stamp: FileStamp,
+        hash: Option<u64>,
+        data: &[u8],
+    ) -> Self {
+        let mut file = Self::new(path, member, stamp);
+        file.hash = hash;
+
+        match decode_web_font(data) {
+            None => file.parse(data),
+            Some(Ok(decoded)) => {
+                file.container = if data.starts_with(WOFF_SIGNATURE) {
+                    FontContainer::Woff
+                } else {
+                    FontContainer::Woff2
+                };
+                file.parse(&decoded);
+            }
+            Some(Err(err)) => {
+                let source = FontSource { path: file.path.clone(), member: file.member.clone() };
+                eprintln!("Error decoding web font \"{}\": {:#}", source, err)
             }
         }
 
-        None
+        file
+    }
+
+    fn details(&self, face_idx: usize) -> String {
+        let face = &self.faces[face_idx];
+
+        // 16.16 fixed point, usually meant to be read as 3 decimal digits,
+        // rounded to the nearest, so 0xFFFF carries into the integer part
+        let thousandths = (u64::from(self.revision) * 1000 + 0x8000) >> 16;
+        let mut details = format!("version {}.{:03}", thousandths / 1000, thousandths % 1000);
+
+        details.push_str(match self.format {
+            FontFormat::TrueType => ", TrueType",
+            FontFormat::Cff => ", OpenType CFF",
+        });
+
+        details.push_str(match self.container {
+            FontContainer::Sfnt => "",
+            FontContainer::Woff => ", WOFF",
+            FontContainer::Woff2 => ", WOFF2",
+        });
+
+        if self.is_collection {
+            details.push_str(&format!(", face {} in collection", face.index));
+        }
+
+        details.push_str(", ");
+        details.push_str(&face.details());
+
+        details
+    }
+
+    fn parse(&mut self, data: &[u8]) {
+        // would return None for a regular font file (.ttf / .otf)
+        let num_faces = fonts_in_collection(data);
+        self.is_collection = num_faces.is_some();
+
+        for (index, face) in
+            (0..num_faces.unwrap_or(1)).filter_map(|i| Face::parse(data, i).ok().map(|f| (i, f)))
+        {
+            if face.tables().cff.is_some() || face.tables().cff2.is_some() {
+                self.format = FontFormat::Cff;
+            }
+
+            self.revision = self.revision.max(get_font_revision(&face));
+
+            let mut names: Vec<_> = face
+                .names()
+                .into_iter()
+                .filter_map(|name| {
+                    let kind = NameKind::from_name_id(name.name_id)?;
+                    Some(FontName { name: decode_name(&name)?, kind })
+                })
+                .collect();
+
+            // the same name is usually present for multiple platforms
+            names.sort_unstable();
+            names.dedup();
+
+            let base = FontFace {
+                index,
+                instance: None,
+                names,
+                is_variable: face.is_variable(),
+                weight_range: weight_range(&face),
+                weight: face.weight().to_number(),
+                width: face.width().to_number(),
+                is_italic: face.is_italic(),
+                is_oblique: face.is_oblique(),
+            };
+
+            // each named instance is a face of its own, sharing family names with the base one
+            let instances: Vec<_> = named_instances(&face)
+                .into_iter()
+                .map(|instance| {
+                    let mut names: Vec<_> = base
+                        .names
+                        .iter()
+                        .filter(|name| {
+                            matches!(name.kind, NameKind::Family | NameKind::TypographicFamily)
+                        })
+                        .cloned()
+                        .chain([
+                            FontName { name: instance.full_name, kind: NameKind::Full },
+                            FontName { name: instance.postscript_name, kind: NameKind::PostScript },
+                        ])
+                        .collect();
+                    names.sort_unstable();
+                    names.dedup();
+
+                    FontFace {
+                        index,
+                        instance: Some(instance.index),
+                        names,
+                        is_variable: true,
+                        weight_range: base.weight_range,
+                        weight: instance.weight.unwrap_or(base.weight),
+                        width: instance.width.unwrap_or(base.width),
+                        is_italic: instance.is_italic.unwrap_or(base.is_italic),
+                        is_oblique: instance.is_oblique.unwrap_or(base.is_oblique),
+                    }
+                })
+                .collect();
+
+            self.faces.push(base);
+            self.faces.extend(instances);
+        }
     }
 }
 
-fn get_font_names(path: &Path) -> (Vec<String>, bool) {
-    let Ok(file) = File::open(path) else {
-        eprintln!("Error reading file \"{}\"", path.display());
-        return (Vec::new(), false);
+/// Decode a WOFF or WOFF2 file into a plain font file,
+/// returns the font data and a fitting extension, or `None` for other files
+pub fn decode_web_font_file(path: &Path) -> Result<Option<(Vec<u8>, &'static str)>> {
+    let mapped =
+        map_file(path).with_context(|| format!("Error reading file \"{}\"", path.display()))?;
+
+    let Some(decoded) = decode_web_font(&mapped) else {
+        return Ok(None);
     };
 
-    // memmap so we don't have to read the whole file
-    let Ok(mapped) = (unsafe { Mmap::map(&file) }) else {
-        eprintln!("Error reading file \"{}\"", path.display());
-        return (Vec::new(), false);
+    let decoded =
+        decoded.with_context(|| format!("Error decoding web font \"{}\"", path.display()))?;
+
+    let ext = font_extension(&decoded);
+
+    Ok(Some((decoded, ext)))
+}
+
+/// Content of a font file and its extension, web fonts are decoded
+pub fn read_font_file(source: &FontSource) -> Result<(Vec<u8>, &'static str)> {
+    with_font_data(source, |data| Ok((data.to_owned(), font_extension(data))))
+}
+
+fn font_extension(data: &[u8]) -> &'static str {
+    match data.get(..4) {
+        Some(b"OTTO") => "otf",
+        Some(b"ttcf") => "ttc",
+        _ => "ttf",
+    }
+}
+
+/// Every name of every face a renderer may match, for font data not in a file,
+/// e.g. fonts embedded in subtitles
+pub fn font_names(data: &[u8]) -> Vec<String> {
+    let stamp = FileStamp { size: data.len() as u64, modified: SystemTime::UNIX_EPOCH };
+    let mut file = FontFile::new(PathBuf::new(), None, stamp);
+    file.parse(data);
+
+    let mut names: Vec<_> =
+        file.faces.into_iter().flat_map(|face| face.names).map(|name| name.name).collect();
+    names.sort_unstable();
+    names.dedup();
+
+    names
+}
+
+/// Characters the face has no glyph for
+pub fn missing_chars(
+    source: &FontSource,
+    face_index: u32,
+    chars: impl IntoIterator<Item = char>,
+) -> Result<Vec<char>> {
+    with_font_data(source, |data| {
+        let face = Face::parse(data, face_index)
+            .with_context(|| format!("Error parsing font file \"{}\"", source))?;
+
+        Ok(chars.into_iter().filter(|&c| face.glyph_index(c).is_none()).collect())
+    })
+}
+
+/// The face reduced to glyphs of the characters, as a standalone font file,
+/// returned with its extension (`ttf` or `otf`)
+///
+/// Glyph IDs and `cmap` are kept as is while outlines of other glyphs are dropped,
+/// layout tables like `GSUB` are removed.
+pub fn subset_font(
+    source: &FontSource,
+    face_index: u32,
+    chars: impl IntoIterator<Item = char>,
+) -> Result<(Vec<u8>, &'static str)> {
+    with_font_data(source, |data| {
+        let face = Face::parse(data, face_index)
+            .with_context(|| format!("Error parsing font file \"{}\"", source))?;
+
+        let mut glyphs: Vec<u16> =
+            chars.into_iter().filter_map(|c| face.glyph_index(c)).map(|id| id.0).collect();
+        glyphs.sort_unstable();
+        glyphs.dedup();
+
+        let subset = subsetter::subset(data, face_index, subsetter::Profile::pdf(&glyphs))
+            .with_context(|| format!("Error subsetting font file \"{}\"", source))?;
+
+        let ext = font_extension(&subset);
+
+        Ok((subset, ext))
+    })
+}
+
+// web fonts are decoded first, fonts in archives are read into memory
+fn with_font_data<T>(source: &FontSource, f: impl FnOnce(&[u8]) -> Result<T>) -> Result<T> {
+    let (read, mapped);
+    let data: &[u8] = match &source.member {
+        Some(member) => {
+            read = read_member(&source.path, member)?;
+            &read
+        }
+        None => {
+            mapped = map_file(&source.path)
+                .with_context(|| format!("Error reading file \"{}\"", source.path.display()))?;
+            &mapped
+        }
     };
 
-    // would return None for a regular font file (.ttf / .otf)
-    let num_faces = fonts_in_collection(&mapped).unwrap_or(1);
-
-    let mut is_variable = false;
-
-    let names = (0..num_faces)
-        .filter_map(|i| Face::parse(&mapped, i).ok())
-        // no sane people would put variable and
-        // non-variable fonts in a single collection
-        .inspect(|face| is_variable = face.is_variable())
-        .flat_map(|face| face.names())
-        .filter(|name| name.name_id == FULL_NAME)
-        .filter_map(|name| {
-            // try UTF-16 first
-            name.to_string().or_else(
-                // then try UTF-8
-                || String::from_utf8(name.name.to_vec()).ok(),
-            )
-        })
-        .collect();
+    match decode_web_font(data) {
+        None => f(data),
+        Some(decoded) => {
+            f(&decoded.with_context(|| format!("Error decoding web font \"{}\"", source))?)
+        }
+    }
+}
+
+fn map_file(path: &Path) -> Option<Mmap> {
+    let file = File::open(path).ok()?;
+    // memmap so we don't have to read the whole file
+    unsafe { Mmap::map(&file) }.ok()
+}
+
+fn hash_file(path: &Path) -> Option<u64> {
+    map_file(path).map(|mapped| xxh3_64(&mapped))
+}
 
-    (names, is_variable)
+/// `fontRevision` in `head` table, as 16.16 fixed point number
+fn get_font_revision(face: &Face) -> u32 {
+    face.raw_face()
+        .table(Tag::from_bytes(b"head"))
+        .and_then(|head| head.get(4..8))
+        .map_or(0, |bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
 }
@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+use anyhow::Result;
+use bincode::config::standard;
+use bincode::{
+    Decode, Encode, decode_from_slice, decode_from_std_read, encode_into_std_write, encode_to_vec,
+};
+
+use super::FontFile;
+
+// layout of a cache file:
+// magic number, header, then a list of separately encoded entries,
+// each one starting with the path of the font file it describes,
+// so even entries of an unknown layout can be located on disk
+const MAGIC: &[u8; 8] = b"FNTLDRC\0";
+
+/// Bump this whenever the layout of `FontFile` changes
+const VERSION: u32 = 9;
+
+// never change this, or older versions can't tell what built the cache
+#[derive(Encode, Decode)]
+struct Header {
+    version: u32,
+    generator: String,
+}
+
+pub enum Contents {
+    Current(Vec<FontFile>),
+    /// Built by an older version, only paths are recovered
+    Outdated {
+        generator: String,
+        paths: Vec<PathBuf>,
+    },
+    Newer {
+        generator: String,
+    },
+}
+
+pub fn read(reader: &mut (impl Read + Seek)) -> Result<Contents> {
+    let mut magic = [0; MAGIC.len()];
+    if reader.read_exact(&mut magic).is_err() || &magic != MAGIC {
+        // caches built by 0.2.2 or earlier don't have a header
+        reader.seek(SeekFrom::Start(0))?;
+        let legacy: LegacyFontProviders = decode_from_std_read(reader, standard())?;
+        return Ok(Contents::Outdated {
+            generator: String::from("0.2.2 or earlier"),
+            paths: legacy.files.into_iter().map(|file| file.path).collect(),
+        });
+    }
+
+    let header: Header = decode_from_std_read(reader, standard())?;
+    let entries: Vec<Vec<u8>> = decode_from_std_read(reader, standard())?;
+
+    if header.version > VERSION {
+        return Ok(Contents::Newer { generator: header.generator });
+    }
+
+    if header.version < VERSION {
+        let paths = entries
+            .iter()
+            .map(|entry| Ok(decode_from_slice(entry, standard())?.0))
+            .collect::<Result<_>>()?;
+        return Ok(Contents::Outdated { generator: header.generator, paths });
+    }
+
+    let files = entries
+        .iter()
+        .map(|entry| Ok(decode_from_slice(entry, standard())?.0))
+        .collect::<Result<_>>()?;
+
+    Ok(Contents::Current(files))
+}
+
+pub fn write(writer: &mut impl Write, files: &[FontFile]) -> Result<()> {
+    let header = Header { version: VERSION, generator: env!("CARGO_PKG_VERSION").to_owned() };
+
+    let entries =
+        files.iter().map(|file| encode_to_vec(file, standard())).collect::<Result<Vec<_>, _>>()?;
+
+    writer.write_all(MAGIC)?;
+    encode_into_std_write(header, writer, standard())?;
+    encode_into_std_write(entries, writer, standard())?;
+
+    Ok(())
+}
+
+// bare layout used before the header was introduced
+
+#[derive(Decode)]
+struct LegacyFontFile {
+    path: PathBuf,
+    _names: Vec<String>,
+    _is_variable: bool,
+}
+
+#[derive(Decode)]
+struct LegacyFontProviders {
+    files: Vec<LegacyFontFile>,
+    _map: HashMap<String, usize>,
+}
@@ -0,0 +1,55 @@
+use encoding_rs::{BIG5, EUC_KR, Encoding, GBK, MACINTOSH, SHIFT_JIS, UTF_16BE, WINDOWS_1252};
+use ttf_parser::PlatformId;
+use ttf_parser::name::Name;
+
+/// Decode a name record according to its platform and encoding IDs
+pub fn decode_name(name: &Name) -> Option<String> {
+    let decoded = match (name.platform_id, name.encoding_id) {
+        (PlatformId::Unicode, _) => decode(UTF_16BE, name.name),
+
+        (PlatformId::Windows, 0 | 1 | 10) => decode(UTF_16BE, name.name),
+        (PlatformId::Windows, 2) => decode_wide_mbcs(SHIFT_JIS, name.name),
+        (PlatformId::Windows, 3) => decode_wide_mbcs(GBK, name.name),
+        (PlatformId::Windows, 4) => decode_wide_mbcs(BIG5, name.name),
+        (PlatformId::Windows, 5) => decode_wide_mbcs(EUC_KR, name.name),
+
+        (PlatformId::Macintosh, 0) => decode(MACINTOSH, name.name),
+        (PlatformId::Macintosh, 1) => decode(SHIFT_JIS, name.name),
+        (PlatformId::Macintosh, 2) => decode(BIG5, name.name),
+        (PlatformId::Macintosh, 3) => decode(EUC_KR, name.name),
+        (PlatformId::Macintosh, 25) => decode(GBK, name.name),
+
+        (PlatformId::Iso, 0) => decode(WINDOWS_1252, name.name).filter(|name| name.is_ascii()),
+        (PlatformId::Iso, 1) => decode(UTF_16BE, name.name),
+        (PlatformId::Iso, 2) => decode(WINDOWS_1252, name.name),
+
+        // unknown combination, at least try UTF-8
+        _ => String::from_utf8(name.name.to_vec()).ok(),
+    }?;
+
+    let decoded = decoded.trim_end_matches('\0');
+
+    if decoded.is_empty() { None } else { Some(decoded.to_owned()) }
+}
+
+fn decode(encoding: &'static Encoding, bytes: &[u8]) -> Option<String> {
+    encoding.decode_without_bom_handling_and_without_replacement(bytes).map(String::from)
+}
+
+// Windows platform legacy encodings store each character in a 16-bit big-endian code unit,
+// with single-byte characters zero-padded, though some fonts store plain byte strings instead
+fn decode_wide_mbcs(encoding: &'static Encoding, bytes: &[u8]) -> Option<String> {
+    if bytes.len().is_multiple_of(2) {
+        let packed: Vec<u8> = bytes
+            .chunks_exact(2)
+            .flat_map(|unit| if unit[0] == 0 { &unit[1..] } else { unit })
+            .copied()
+            .collect();
+
+        if let Some(decoded) = decode(encoding, &packed) {
+            return Some(decoded);
+        }
+    }
+
+    decode(encoding, bytes)
+}
@@ -0,0 +1,177 @@
+// named instances of variable fonts
+// https://learn.microsoft.com/en-us/typography/opentype/spec/fvar
+// https://learn.microsoft.com/en-us/typography/opentype/spec/stat
+
+use ttf_parser::name_id::{FAMILY, TYPOGRAPHIC_FAMILY, VARIATIONS_POST_SCRIPT_NAME_PREFIX};
+use ttf_parser::stat::AxisValueSubtable;
+use ttf_parser::{Face, Fixed, Tag};
+
+use super::encoding::decode_name;
+
+const WEIGHT_AXIS: Tag = Tag::from_bytes(b"wght");
+const WIDTH_AXIS: Tag = Tag::from_bytes(b"wdth");
+const ITALIC_AXIS: Tag = Tag::from_bytes(b"ital");
+const SLANT_AXIS: Tag = Tag::from_bytes(b"slnt");
+
+pub struct NamedInstance {
+    pub index: u16,
+    pub full_name: String,
+    pub postscript_name: String,
+    pub weight: Option<u16>,
+    pub width: Option<u16>,
+    pub is_italic: Option<bool>,
+    pub is_oblique: Option<bool>,
+}
+
+/// Range of the `wght` axis, if there is one
+pub fn weight_range(face: &Face) -> Option<(u16, u16)> {
+    face.variation_axes()
+        .into_iter()
+        .find(|axis| axis.tag == WEIGHT_AXIS)
+        .map(|axis| (to_weight(axis.min_value), to_weight(axis.max_value)))
+}
+
+/// Named instances listed in `fvar`, subfamily names missing in `name`
+/// are made up from `STAT` axis value names instead
+pub fn named_instances(face: &Face) -> Vec<NamedInstance> {
+    let Some(fvar) = face.raw_face().table(Tag::from_bytes(b"fvar")) else {
+        return Vec::new();
+    };
+
+    let read_u16 = |pos: usize| fvar.get(pos..pos + 2).map(|b| u16::from_be_bytes([b[0], b[1]]));
+    let read_fixed = |pos: usize| {
+        fvar.get(pos..pos + 4)
+            .map(|b| i32::from_be_bytes([b[0], b[1], b[2], b[3]]) as f32 / 65536.0)
+    };
+
+    let (Some(axes_offset), Some(axis_count), Some(axis_size), Some(count), Some(size)) =
+        (read_u16(4), read_u16(8), read_u16(10), read_u16(12), read_u16(14))
+    else {
+        return Vec::new();
+    };
+
+    let axis_count = usize::from(axis_count);
+    let size = usize::from(size);
+    // the PostScript name ID is optional
+    let has_postscript_name = size >= axis_count * 4 + 6;
+
+    let tags: Vec<_> = face.variation_axes().into_iter().map(|axis| axis.tag).collect();
+    if tags.len() != axis_count {
+        return Vec::new();
+    }
+
+    let family = find_name(face, TYPOGRAPHIC_FAMILY).or_else(|| find_name(face, FAMILY));
+    let Some(family) = family else {
+        return Vec::new();
+    };
+
+    let postscript_prefix = find_name(face, VARIATIONS_POST_SCRIPT_NAME_PREFIX)
+        .unwrap_or_else(|| family.replace(' ', ""));
+
+    let start = usize::from(axes_offset) + axis_count * usize::from(axis_size);
+
+    (0..count)
+        .filter_map(|index| {
+            let pos = start + usize::from(index) * size;
+
+            let subfamily_id = read_u16(pos)?;
+            let coords = (0..axis_count)
+                .map(|axis| read_fixed(pos + 4 + axis * 4))
+                .collect::<Option<Vec<_>>>()?;
+
+            let subfamily = find_name(face, subfamily_id)
+                .or_else(|| subfamily_from_stat(face, &tags, &coords))?;
+
+            let postscript_name = has_postscript_name
+                .then(|| read_u16(pos + 4 + axis_count * 4))
+                .flatten()
+                .filter(|&id| id != 0xFFFF)
+                .and_then(|id| find_name(face, id))
+                .unwrap_or_else(|| {
+                    let suffix: String =
+                        subfamily.chars().filter(char::is_ascii_alphanumeric).collect();
+                    format!("{}-{}", postscript_prefix, suffix)
+                });
+
+            let coord = |tag| tags.iter().position(|&t| t == tag).map(|idx| coords[idx]);
+
+            Some(NamedInstance {
+                index,
+                full_name: format!("{} {}", family, subfamily),
+                postscript_name,
+                weight: coord(WEIGHT_AXIS).map(to_weight),
+                width: coord(WIDTH_AXIS).map(to_width_class),
+                is_italic: coord(ITALIC_AXIS).map(|value| value >= 1.0),
+                is_oblique: coord(SLANT_AXIS).map(|value| value != 0.0),
+            })
+        })
+        .collect()
+}
+
+// join names of non-elidable axis values in the order of axes,
+// or the elided fallback name if everything is elided
+fn subfamily_from_stat(face: &Face, tags: &[Tag], coords: &[f32]) -> Option<String> {
+    let stat = face.tables().stat?;
+
+    let mut parts = Vec::new();
+
+    for (axis_idx, record) in stat.axes.into_iter().enumerate() {
+        let Some(value) = tags.iter().position(|&tag| tag == record.tag).map(|idx| coords[idx])
+        else {
+            continue;
+        };
+
+        // format 4 may refer to multiple axes, not handled
+        let subtable = stat.subtables().find(|subtable| {
+            let axis_index = match subtable {
+                AxisValueSubtable::Format1(subtable) => subtable.axis_index,
+                AxisValueSubtable::Format2(subtable) => subtable.axis_index,
+                AxisValueSubtable::Format3(subtable) => subtable.axis_index,
+                AxisValueSubtable::Format4(_) => return false,
+            };
+            usize::from(axis_index) == axis_idx && subtable.contains(Fixed(value))
+        });
+
+        if let Some(subtable) = subtable
+            && !subtable.is_elidable()
+            && let Some(name) = find_name(face, subtable.name_id())
+        {
+            parts.push((record.ordering, name));
+        }
+    }
+
+    if parts.is_empty() {
+        return stat.fallback_name_id.and_then(|id| find_name(face, id));
+    }
+
+    parts.sort_by_key(|(ordering, _)| *ordering);
+
+    Some(parts.into_iter().map(|(_, name)| name).collect::<Vec<_>>().join(" "))
+}
+
+/// First decodable name with the ID, English names preferred
+fn find_name(face: &Face, name_id: u16) -> Option<String> {
+    const ENGLISH_US: u16 = 0x0409;
+
+    let names = || face.names().into_iter().filter(move |name| name.name_id == name_id);
+
+    names()
+        .filter(|name| name.language_id == ENGLISH_US)
+        .chain(names())
+        .find_map(|name| decode_name(&name))
+}
+
+fn to_weight(value: f32) -> u16 {
+    value.round().clamp(1.0, 1000.0) as u16
+}
+
+// nearest `usWidthClass` of a `wdth` axis percentage
+fn to_width_class(value: f32) -> u16 {
+    const PERCENTAGES: [f32; 9] = [50.0, 62.5, 75.0, 87.5, 100.0, 112.5, 125.0, 150.0, 200.0];
+
+    PERCENTAGES
+        .iter()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| (*a - value).abs().total_cmp(&(*b - value).abs()))
+        .map_or(5, |(idx, _)| idx as u16 + 1)
+}
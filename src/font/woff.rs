@@ -0,0 +1,1104 @@
+// WOFF / WOFF2 to plain sfnt (TrueType / OpenType) conversion
+// https://www.w3.org/TR/WOFF/
+// https://www.w3.org/TR/WOFF2/
+
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::io::Read;
+
+use anyhow::{Context, Result, bail, ensure};
+use brotli_decompressor::Decompressor;
+use flate2::read::ZlibDecoder;
+
+pub const WOFF_SIGNATURE: &[u8; 4] = b"wOFF";
+pub const WOFF2_SIGNATURE: &[u8; 4] = b"wOF2";
+
+const COLLECTION_TAG: u32 = u32::from_be_bytes(*b"ttcf");
+const GLYF_TAG: u32 = u32::from_be_bytes(*b"glyf");
+const LOCA_TAG: u32 = u32::from_be_bytes(*b"loca");
+const HMTX_TAG: u32 = u32::from_be_bytes(*b"hmtx");
+const HHEA_TAG: u32 = u32::from_be_bytes(*b"hhea");
+const HEAD_TAG: u32 = u32::from_be_bytes(*b"head");
+
+// far beyond any real font, sizes in headers are checked against it before allocating
+const MAX_SFNT_SIZE: usize = 512 << 20;
+
+/// Convert WOFF or WOFF2 data to sfnt, returns `None` for other data
+pub fn decode_web_font(data: &[u8]) -> Option<Result<Vec<u8>>> {
+    match data.get(..4)? {
+        signature if signature == WOFF_SIGNATURE => Some(decode_woff(data)),
+        signature if signature == WOFF2_SIGNATURE => Some(decode_woff2(data)),
+        _ => None,
+    }
+}
+
+struct Table {
+    tag: u32,
+    data: Vec<u8>,
+}
+
+fn decode_woff(data: &[u8]) -> Result<Vec<u8>> {
+    let mut header = Reader::new(data);
+    header.skip(4)?;
+    let flavor = header.u32()?;
+    header.skip(4)?;
+    let num_tables = header.u16()?;
+    header.skip(2)?;
+    let total_sfnt_size = header.u32()? as usize;
+    // skip version and metadata / private data blocks
+    header.skip(4 + 4 * 5)?;
+
+    ensure!(total_sfnt_size <= MAX_SFNT_SIZE, "WOFF font too large");
+
+    let mut tables = Vec::with_capacity(num_tables.into());
+    let mut decoded_size = 0;
+
+    for _ in 0..num_tables {
+        let tag = header.u32()?;
+        let offset = header.u32()? as usize;
+        let comp_length = header.u32()? as usize;
+        let orig_length = header.u32()? as usize;
+        header.skip(4)?;
+
+        let compressed =
+            data.get(offset..offset + comp_length).context("WOFF table data out of bounds")?;
+
+        decoded_size += orig_length;
+        ensure!(decoded_size <= total_sfnt_size, "WOFF tables larger than the font");
+
+        let table = if comp_length < orig_length {
+            let mut table = Vec::with_capacity(orig_length);
+            // one more byte than declared is enough to tell the length is wrong
+            ZlibDecoder::new(compressed).take(orig_length as u64 + 1).read_to_end(&mut table)?;
+            table
+        } else {
+            compressed.to_vec()
+        };
+
+        ensure!(table.len() == orig_length, "WOFF table length mismatch");
+
+        tables.push(Table { tag, data: table });
+    }
+
+    let fonts = [(flavor, (0..tables.len()).collect())];
+
+    Ok(write_sfnt(&fonts, &tables, false))
+}
+
+fn decode_woff2(data: &[u8]) -> Result<Vec<u8>> {
+    const KNOWN_TAGS: [&[u8; 4]; 63] = [
+        b"cmap", b"head", b"hhea", b"hmtx", b"maxp", b"name", b"OS/2", b"post", b"cvt ", b"fpgm",
+        b"glyf", b"loca", b"prep", b"CFF ", b"VORG", b"EBDT", b"EBLC", b"gasp", b"hdmx", b"kern",
+        b"LTSH", b"PCLT", b"VDMX", b"vhea", b"vmtx", b"BASE", b"GDEF", b"GPOS", b"GSUB", b"EBSC",
+        b"JSTF", b"MATH", b"CBDT", b"CBLC", b"COLR", b"CPAL", b"SVG ", b"sbix", b"acnt", b"avar",
+        b"bdat", b"bloc", b"bsln", b"cvar", b"fdsc", b"feat", b"fmtx", b"fvar", b"gvar", b"hsty",
+        b"just", b"lcar", b"mort", b"morx", b"opbd", b"prop", b"trak", b"Zapf", b"Silf", b"Glat",
+        b"Gloc", b"Feat", b"Sill",
+    ];
+
+    struct TableEntry {
+        tag: u32,
+        orig_length: usize,
+        transform_length: Option<usize>,
+    }
+
+    let mut reader = Reader::new(data);
+    reader.skip(4)?;
+    let flavor = reader.u32()?;
+    reader.skip(4)?;
+    let num_tables = reader.u16()?;
+    reader.skip(2)?;
+    let total_sfnt_size = reader.u32()? as usize;
+    let total_compressed_size = reader.u32()? as usize;
+    // skip version and metadata / private data blocks
+    reader.skip(4 + 4 * 5)?;
+
+    ensure!(total_sfnt_size <= MAX_SFNT_SIZE, "WOFF2 font too large");
+
+    let mut entries = Vec::with_capacity(num_tables.into());
+
+    for _ in 0..num_tables {
+        let flags = reader.u8()?;
+        let tag = match flags & 0x3F {
+            0x3F => reader.u32()?,
+            idx => u32::from_be_bytes(*KNOWN_TAGS[usize::from(idx)]),
+        };
+        let transform_version = flags >> 6;
+        let orig_length = reader.base128()? as usize;
+
+        // for glyf and loca, version 0 is the transformed one
+        let is_transformed = if tag == GLYF_TAG || tag == LOCA_TAG {
+            transform_version == 0
+        } else {
+            transform_version != 0
+        };
+
+        let transform_length = if is_transformed { Some(reader.base128()? as usize) } else { None };
+
+        entries.push(TableEntry { tag, orig_length, transform_length });
+    }
+
+    let is_collection = flavor == COLLECTION_TAG;
+
+    let fonts = if is_collection {
+        reader.skip(4)?;
+        let num_fonts = reader.uint255()?;
+        (0..num_fonts)
+            .map(|_| {
+                let num_tables = reader.uint255()?;
+                let flavor = reader.u32()?;
+                let indices = (0..num_tables)
+                    .map(|_| {
+                        let idx = usize::from(reader.uint255()?);
+                        ensure!(idx < entries.len(), "WOFF2 table index out of bounds");
+                        Ok(idx)
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Ok((flavor, indices))
+            })
+            .collect::<Result<Vec<_>>>()?
+    } else {
+        vec![(flavor, (0..entries.len()).collect())]
+    };
+
+    // tables are stored one after another, some of them transformed
+    let stream_size = entries
+        .iter()
+        .map(|entry| entry.transform_length.unwrap_or(entry.orig_length))
+        .try_fold(0usize, usize::checked_add)
+        .filter(|&size| size <= total_sfnt_size)
+        .context("WOFF2 tables larger than the font")?;
+
+    let compressed = reader.bytes(total_compressed_size)?;
+    let mut decompressed = Vec::new();
+    Decompressor::new(compressed, 4096)
+        .take(stream_size as u64)
+        .read_to_end(&mut decompressed)
+        .context("Error decompressing WOFF2 data")?;
+
+    // split the decompressed stream into tables
+    let mut stream = Reader::new(&decompressed);
+    let mut tables: Vec<_> = entries
+        .iter()
+        .map(|entry| {
+            let length = entry.transform_length.unwrap_or(entry.orig_length);
+            Ok(Table { tag: entry.tag, data: stream.bytes(length)?.to_vec() })
+        })
+        .collect::<Result<_>>()?;
+
+    // undo transforms, each font in a collection refers to its own glyf / loca / hmtx,
+    // though they may be shared between fonts, so only do it once for each table
+    let mut x_mins_of_glyf: HashMap<usize, Vec<i16>> = HashMap::new();
+    let mut done_hmtx = Vec::new();
+
+    for (_, indices) in &fonts {
+        let find = |tag| indices.iter().copied().find(|&idx| entries[idx].tag == tag);
+
+        let glyf = find(GLYF_TAG);
+        let loca = find(LOCA_TAG);
+
+        if let (Some(glyf), Some(loca)) = (glyf, loca)
+            && entries[glyf].transform_length.is_some()
+            && !x_mins_of_glyf.contains_key(&glyf)
+        {
+            let (glyf_data, loca_data, x_mins) = reconstruct_glyf(&tables[glyf].data)?;
+            tables[glyf].data = glyf_data;
+            tables[loca].data = loca_data;
+            x_mins_of_glyf.insert(glyf, x_mins);
+        }
+
+        if let Some(hmtx) = find(HMTX_TAG)
+            && entries[hmtx].transform_length.is_some()
+            && !done_hmtx.contains(&hmtx)
+        {
+            let glyf = glyf.context("WOFF2 transformed hmtx without glyf")?;
+            let loca = loca.context("WOFF2 transformed hmtx without loca")?;
+
+            let x_mins = match x_mins_of_glyf.entry(glyf) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    let head = find(HEAD_TAG).context("WOFF2 font without head")?;
+                    entry.insert(read_x_mins(
+                        &tables[glyf].data, &tables[loca].data, &tables[head].data,
+                    )?)
+                }
+            };
+
+            let hhea = find(HHEA_TAG).context("WOFF2 transformed hmtx without hhea")?;
+            let num_h_metrics = Reader::new(&tables[hhea].data).at(34)?.u16()?;
+            tables[hmtx].data = reconstruct_hmtx(&tables[hmtx].data, num_h_metrics, x_mins)?;
+            done_hmtx.push(hmtx);
+        }
+    }
+
+    // reconstructed glyf table is not necessarily the same as the original one
+    for (entry, table) in entries.iter().zip(&tables) {
+        ensure!(
+            table.data.len() == entry.orig_length || entry.tag == GLYF_TAG,
+            "WOFF2 table length mismatch"
+        );
+    }
+
+    Ok(write_sfnt(&fonts, &tables, is_collection))
+}
+
+/// Returns glyf table, loca table and xMin of each glyph
+fn reconstruct_glyf(data: &[u8]) -> Result<(Vec<u8>, Vec<u8>, Vec<i16>)> {
+    const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+    const WE_HAVE_A_SCALE: u16 = 0x0008;
+    const MORE_COMPONENTS: u16 = 0x0020;
+    const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+    const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+    const WE_HAVE_INSTRUCTIONS: u16 = 0x0100;
+    const OVERLAP_SIMPLE: u8 = 0x40;
+
+    let mut header = Reader::new(data);
+    header.skip(2)?;
+    let option_flags = header.u16()?;
+    let num_glyphs = usize::from(header.u16()?);
+    let index_format = header.u16()?;
+
+    let mut sizes = [0; 7];
+    for size in &mut sizes {
+        *size = header.u32()? as usize;
+    }
+
+    let mut offset = 36;
+    let mut next_stream = |size: usize| {
+        let stream = data.get(offset..offset + size).context("WOFF2 glyf stream out of bounds");
+        offset += size;
+        stream.map(Reader::new)
+    };
+
+    let mut n_contour_stream = next_stream(sizes[0])?;
+    let mut n_points_stream = next_stream(sizes[1])?;
+    let mut flag_stream = next_stream(sizes[2])?;
+    let mut glyph_stream = next_stream(sizes[3])?;
+    let mut composite_stream = next_stream(sizes[4])?;
+    let mut bbox_stream = next_stream(sizes[5])?;
+    let mut instruction_stream = next_stream(sizes[6])?;
+
+    let overlap_bitmap =
+        if option_flags & 1 != 0 { next_stream(num_glyphs.div_ceil(8))?.data } else { &[] };
+
+    let bbox_bitmap = bbox_stream.bytes(4 * num_glyphs.div_ceil(32))?;
+    let has_bbox = |idx: usize| bbox_bitmap[idx / 8] & (0x80 >> (idx % 8)) != 0;
+
+    let mut glyf = Vec::new();
+    let mut offsets = Vec::with_capacity(num_glyphs + 1);
+    let mut x_mins = Vec::with_capacity(num_glyphs);
+
+    for idx in 0..num_glyphs {
+        offsets.push(glyf.len());
+
+        let n_contours = n_contour_stream.i16()?;
+
+        if n_contours == 0 {
+            ensure!(!has_bbox(idx), "WOFF2 empty glyph with bbox");
+            x_mins.push(0);
+            continue;
+        }
+
+        if n_contours < 0 {
+            // composite glyph, copied as is
+            ensure!(has_bbox(idx), "WOFF2 composite glyph without bbox");
+
+            let mut composite = composite_stream.clone();
+            let mut have_instructions = false;
+            loop {
+                let flags = composite.u16()?;
+                have_instructions |= flags & WE_HAVE_INSTRUCTIONS != 0;
+                let mut size = 2 + if flags & ARG_1_AND_2_ARE_WORDS != 0 { 4 } else { 2 };
+                if flags & WE_HAVE_A_SCALE != 0 {
+                    size += 2;
+                } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+                    size += 4;
+                } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+                    size += 8;
+                }
+                composite.skip(size)?;
+                if flags & MORE_COMPONENTS == 0 {
+                    break;
+                }
+            }
+            let components = composite_stream.bytes(composite.pos - composite_stream.pos)?;
+
+            let bbox = bbox_stream.bytes(8)?;
+            x_mins.push(i16::from_be_bytes([bbox[0], bbox[1]]));
+
+            glyf.extend_from_slice(&(-1i16).to_be_bytes());
+            glyf.extend_from_slice(bbox);
+            glyf.extend_from_slice(components);
+
+            if have_instructions {
+                let length = glyph_stream.uint255()?;
+                glyf.extend_from_slice(&length.to_be_bytes());
+                glyf.extend_from_slice(instruction_stream.bytes(length.into())?);
+            }
+        } else {
+            // simple glyph
+            let mut end_points = Vec::with_capacity(n_contours as usize);
+            let mut num_points = 0usize;
+            for _ in 0..n_contours {
+                num_points += usize::from(n_points_stream.uint255()?);
+                ensure!(num_points <= 0x10000, "WOFF2 glyph has too many points");
+                let end_point = num_points.checked_sub(1).context("WOFF2 empty contour")?;
+                end_points.push(end_point as u16);
+            }
+
+            let mut points = Vec::with_capacity(num_points);
+            let (mut x, mut y) = (0i32, 0i32);
+            for _ in 0..num_points {
+                let flag = flag_stream.u8()?;
+                let (dx, dy) = decode_triplet(flag & 0x7F, &mut glyph_stream)?;
+                x += dx;
+                y += dy;
+                points.push((x, y, flag & 0x80 == 0));
+            }
+
+            let instruction_length = glyph_stream.uint255()?;
+            let instructions = instruction_stream.bytes(instruction_length.into())?;
+
+            let bbox = if has_bbox(idx) {
+                let mut bbox = Reader::new(bbox_stream.bytes(8)?);
+                [bbox.i16()?, bbox.i16()?, bbox.i16()?, bbox.i16()?]
+            } else {
+                let clamp = |v: i32| v.clamp(i16::MIN.into(), i16::MAX.into()) as i16;
+                let min_x = points.iter().map(|p| p.0).min().unwrap_or(0);
+                let min_y = points.iter().map(|p| p.1).min().unwrap_or(0);
+                let max_x = points.iter().map(|p| p.0).max().unwrap_or(0);
+                let max_y = points.iter().map(|p| p.1).max().unwrap_or(0);
+                [clamp(min_x), clamp(min_y), clamp(max_x), clamp(max_y)]
+            };
+            x_mins.push(bbox[0]);
+
+            glyf.extend_from_slice(&n_contours.to_be_bytes());
+            for value in bbox {
+                glyf.extend_from_slice(&value.to_be_bytes());
+            }
+            for end_point in end_points {
+                glyf.extend_from_slice(&end_point.to_be_bytes());
+            }
+            glyf.extend_from_slice(&instruction_length.to_be_bytes());
+            glyf.extend_from_slice(instructions);
+
+            let is_overlapping =
+                overlap_bitmap.get(idx / 8).is_some_and(|b| b & (0x80 >> (idx % 8)) != 0);
+            encode_points(&points, if is_overlapping { OVERLAP_SIMPLE } else { 0 }, &mut glyf);
+        }
+
+        // short loca format requires even offsets
+        glyf.resize(glyf.len().next_multiple_of(if index_format == 0 { 2 } else { 4 }), 0);
+    }
+
+    offsets.push(glyf.len());
+
+    let loca = if index_format == 0 {
+        ensure!(glyf.len() / 2 <= u16::MAX.into(), "WOFF2 glyf too large for short loca format");
+        offsets.iter().flat_map(|&offset| ((offset / 2) as u16).to_be_bytes()).collect()
+    } else {
+        offsets.iter().flat_map(|&offset| (offset as u32).to_be_bytes()).collect()
+    };
+
+    Ok((glyf, loca, x_mins))
+}
+
+fn decode_triplet(flag: u8, stream: &mut Reader) -> Result<(i32, i32)> {
+    fn with_sign(flag: u8, value: i32) -> i32 {
+        if flag & 1 != 0 { value } else { -value }
+    }
+
+    let flag_i = i32::from(flag);
+
+    let (dx, dy) = if flag < 10 {
+        let b0 = i32::from(stream.u8()?);
+        (0, with_sign(flag, ((flag_i & 14) << 7) + b0))
+    } else if flag < 20 {
+        let b0 = i32::from(stream.u8()?);
+        (with_sign(flag, (((flag_i - 10) & 14) << 7) + b0), 0)
+    } else if flag < 84 {
+        let b0 = flag_i - 20;
+        let b1 = i32::from(stream.u8()?);
+        (
+            with_sign(flag, 1 + (b0 & 0x30) + (b1 >> 4)),
+            with_sign(flag >> 1, 1 + ((b0 & 0x0C) << 2) + (b1 & 0x0F)),
+        )
+    } else if flag < 120 {
+        let b0 = flag_i - 84;
+        let b1 = i32::from(stream.u8()?);
+        let b2 = i32::from(stream.u8()?);
+        (
+            with_sign(flag, 1 + ((b0 / 12) << 8) + b1),
+            with_sign(flag >> 1, 1 + (((b0 % 12) >> 2) << 8) + b2),
+        )
+    } else if flag < 124 {
+        let b1 = i32::from(stream.u8()?);
+        let b2 = i32::from(stream.u8()?);
+        let b3 = i32::from(stream.u8()?);
+        (with_sign(flag, (b1 << 4) + (b2 >> 4)), with_sign(flag >> 1, ((b2 & 0x0F) << 8) + b3))
+    } else {
+        let b1 = i32::from(stream.u8()?);
+        let b2 = i32::from(stream.u8()?);
+        let b3 = i32::from(stream.u8()?);
+        let b4 = i32::from(stream.u8()?);
+        (with_sign(flag, (b1 << 8) + b2), with_sign(flag >> 1, (b3 << 8) + b4))
+    };
+
+    Ok((dx, dy))
+}
+
+/// Write flags and coordinates of a simple glyph
+fn encode_points(points: &[(i32, i32, bool)], first_flag: u8, out: &mut Vec<u8>) {
+    const ON_CURVE: u8 = 0x01;
+    const X_SHORT: u8 = 0x02;
+    const Y_SHORT: u8 = 0x04;
+    const REPEAT: u8 = 0x08;
+    const X_SAME_OR_POSITIVE: u8 = 0x10;
+    const Y_SAME_OR_POSITIVE: u8 = 0x20;
+
+    let mut flags = Vec::with_capacity(points.len());
+    let mut xs = Vec::new();
+    let mut ys = Vec::new();
+
+    let (mut last_x, mut last_y) = (0, 0);
+
+    for (idx, &(x, y, on_curve)) in points.iter().enumerate() {
+        let mut flag = if idx == 0 { first_flag } else { 0 };
+        if on_curve {
+            flag |= ON_CURVE;
+        }
+
+        for (delta, short, same, coords) in [
+            (x - last_x, X_SHORT, X_SAME_OR_POSITIVE, &mut xs),
+            (y - last_y, Y_SHORT, Y_SAME_OR_POSITIVE, &mut ys),
+        ] {
+            if delta == 0 {
+                flag |= same;
+            } else if delta.abs() < 256 {
+                flag |= short;
+                if delta > 0 {
+                    flag |= same;
+                }
+                coords.push(delta.unsigned_abs() as u8);
+            } else {
+                coords.extend_from_slice(&(delta as i16).to_be_bytes());
+            }
+        }
+
+        flags.push(flag);
+        (last_x, last_y) = (x, y);
+    }
+
+    // compress runs of the same flag
+    let mut idx = 0;
+    while idx < flags.len() {
+        let flag = flags[idx];
+        let run = flags[idx..].iter().take(256).take_while(|&&f| f == flag).count();
+        if run > 1 {
+            out.extend_from_slice(&[flag | REPEAT, (run - 1) as u8]);
+        } else {
+            out.push(flag);
+        }
+        idx += run;
+    }
+
+    out.extend_from_slice(&xs);
+    out.extend_from_slice(&ys);
+}
+
+/// xMin of each glyph in a plain glyf table
+fn read_x_mins(glyf: &[u8], loca: &[u8], head: &[u8]) -> Result<Vec<i16>> {
+    let is_long = Reader::new(head).at(50)?.i16()? != 0;
+
+    let offsets: Vec<usize> = if is_long {
+        loca.chunks_exact(4)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize)
+            .collect()
+    } else {
+        loca.chunks_exact(2).map(|b| usize::from(u16::from_be_bytes([b[0], b[1]])) * 2).collect()
+    };
+
+    offsets
+        .windows(2)
+        .map(
+            |range| {
+                if range[0] == range[1] { Ok(0) } else { Reader::new(glyf).at(range[0] + 2)?.i16() }
+            },
+        )
+        .collect()
+}
+
+fn reconstruct_hmtx(data: &[u8], num_h_metrics: u16, x_mins: &[i16]) -> Result<Vec<u8>> {
+    let mut reader = Reader::new(data);
+    let flags = reader.u8()?;
+    let num_h_metrics = usize::from(num_h_metrics);
+    let num_glyphs = x_mins.len();
+    ensure!(num_h_metrics <= num_glyphs, "WOFF2 hmtx has too many metrics");
+
+    let advances = (0..num_h_metrics).map(|_| reader.u16()).collect::<Result<Vec<_>>>()?;
+
+    let proportional_lsbs = if flags & 1 != 0 {
+        x_mins[..num_h_metrics].to_vec()
+    } else {
+        (0..num_h_metrics).map(|_| reader.i16()).collect::<Result<_>>()?
+    };
+
+    let monospaced_lsbs = if flags & 2 != 0 {
+        x_mins[num_h_metrics..].to_vec()
+    } else {
+        (num_h_metrics..num_glyphs).map(|_| reader.i16()).collect::<Result<_>>()?
+    };
+
+    let mut hmtx = Vec::with_capacity(num_h_metrics * 4 + (num_glyphs - num_h_metrics) * 2);
+    for (advance, lsb) in advances.into_iter().zip(proportional_lsbs) {
+        hmtx.extend_from_slice(&advance.to_be_bytes());
+        hmtx.extend_from_slice(&lsb.to_be_bytes());
+    }
+    for lsb in monospaced_lsbs {
+        hmtx.extend_from_slice(&lsb.to_be_bytes());
+    }
+
+    Ok(hmtx)
+}
+
+/// Write a font file, or a collection, each font refers to tables by their indices
+fn write_sfnt(fonts: &[(u32, Vec<usize>)], tables: &[Table], is_collection: bool) -> Vec<u8> {
+    let header_size = if is_collection { 12 + 4 * fonts.len() } else { 0 };
+    let directory_size = |num_tables: usize| 12 + 16 * num_tables;
+    let directories_size: usize =
+        fonts.iter().map(|(_, indices)| directory_size(indices.len())).sum();
+
+    // table data goes after all headers, each table only once
+    let mut offsets = Vec::with_capacity(tables.len());
+    let mut offset = header_size + directories_size;
+    for table in tables {
+        offsets.push(offset);
+        offset += table.data.len().next_multiple_of(4);
+    }
+
+    let mut out = Vec::with_capacity(offset);
+
+    if is_collection {
+        out.extend_from_slice(&COLLECTION_TAG.to_be_bytes());
+        out.extend_from_slice(&0x00010000u32.to_be_bytes());
+        out.extend_from_slice(&(fonts.len() as u32).to_be_bytes());
+        let mut directory_offset = header_size;
+        for (_, indices) in fonts {
+            out.extend_from_slice(&(directory_offset as u32).to_be_bytes());
+            directory_offset += directory_size(indices.len());
+        }
+    }
+
+    for (flavor, indices) in fonts {
+        let mut indices = indices.clone();
+        indices.sort_by_key(|&idx| tables[idx].tag);
+
+        // computed wide and clamped, as crafted files may declare more tables than fit
+        let num_tables = indices.len() as u32;
+        let entry_selector = num_tables.checked_ilog2().unwrap_or(0);
+        let search_range = (1u32 << entry_selector) * 16;
+        let range_shift = (num_tables * 16).saturating_sub(search_range);
+
+        out.extend_from_slice(&flavor.to_be_bytes());
+        for field in [num_tables, search_range, entry_selector, range_shift] {
+            out.extend_from_slice(&(field.min(u16::MAX.into()) as u16).to_be_bytes());
+        }
+
+        for idx in indices {
+            let table = &tables[idx];
+            out.extend_from_slice(&table.tag.to_be_bytes());
+            out.extend_from_slice(&checksum(&table.data).to_be_bytes());
+            out.extend_from_slice(&(offsets[idx] as u32).to_be_bytes());
+            out.extend_from_slice(&(table.data.len() as u32).to_be_bytes());
+        }
+    }
+
+    let mut head_offset = None;
+    for (table, offset) in tables.iter().zip(&offsets) {
+        if table.tag == HEAD_TAG {
+            head_offset = Some(offset);
+        }
+        out.extend_from_slice(&table.data);
+        out.resize(out.len().next_multiple_of(4), 0);
+    }
+
+    // checkSumAdjustment only makes sense for a single font
+    if !is_collection
+        && let Some(&head_offset) = head_offset
+        && out.len() >= head_offset + 12
+    {
+        out[head_offset + 8..head_offset + 12].fill(0);
+        let adjustment = 0xB1B0AFBAu32.wrapping_sub(checksum(&out));
+        out[head_offset + 8..head_offset + 12].copy_from_slice(&adjustment.to_be_bytes());
+    }
+
+    out
+}
+
+fn checksum(data: &[u8]) -> u32 {
+    data.chunks(4).fold(0u32, |sum, chunk| {
+        let mut word = [0; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        sum.wrapping_add(u32::from_be_bytes(word))
+    })
+}
+
+#[derive(Clone)]
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn at(mut self, pos: usize) -> Result<Self> {
+        ensure!(pos <= self.data.len(), "Unexpected end of font data");
+        self.pos = pos;
+        Ok(self)
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos.saturating_add(len))
+            .context("Unexpected end of font data")?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn skip(&mut self, len: usize) -> Result<()> {
+        self.bytes(len).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn i16(&mut self) -> Result<i16> {
+        let bytes = self.bytes(2)?;
+        Ok(i16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// `UIntBase128` in WOFF2
+    fn base128(&mut self) -> Result<u32> {
+        let mut value = 0u32;
+        for idx in 0..5 {
+            let byte = self.u8()?;
+            if idx == 0 && byte == 0x80 {
+                bail!("Invalid UIntBase128 with leading zeros");
+            }
+            if value & 0xFE000000 != 0 {
+                bail!("UIntBase128 overflow");
+            }
+            value = (value << 7) | u32::from(byte & 0x7F);
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        bail!("UIntBase128 too long")
+    }
+
+    /// `255UInt16` in WOFF2
+    fn uint255(&mut self) -> Result<u16> {
+        Ok(match self.u8()? {
+            253 => self.u16()?,
+            254 => u16::from(self.u8()?) + 253 * 2,
+            255 => u16::from(self.u8()?) + 253,
+            code => u16::from(code),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::Compression;
+    use flate2::write::ZlibEncoder;
+    use ttf_parser::{Face, GlyphId, OutlineBuilder, Rect};
+
+    use super::*;
+
+    fn head(index_format: i16) -> Vec<u8> {
+        let mut head = vec![0; 54];
+        head[..4].copy_from_slice(&0x00010000u32.to_be_bytes());
+        head[12..16].copy_from_slice(&0x5F0F3CF5u32.to_be_bytes());
+        head[18..20].copy_from_slice(&1000u16.to_be_bytes());
+        head[50..52].copy_from_slice(&index_format.to_be_bytes());
+        head
+    }
+
+    fn hhea(num_h_metrics: u16) -> Vec<u8> {
+        let mut hhea = vec![0; 36];
+        hhea[..4].copy_from_slice(&0x00010000u32.to_be_bytes());
+        hhea[34..36].copy_from_slice(&num_h_metrics.to_be_bytes());
+        hhea
+    }
+
+    fn maxp(num_glyphs: u16) -> Vec<u8> {
+        [0x00005000u32.to_be_bytes().as_slice(), &num_glyphs.to_be_bytes()].concat()
+    }
+
+    fn woff(tables: &[(&[u8; 4], &[u8], bool)]) -> Vec<u8> {
+        let mut directory = Vec::new();
+        let mut data = Vec::new();
+        let offset = 44 + 20 * tables.len();
+        for (tag, table, compress) in tables {
+            let stored = if *compress {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+                encoder.write_all(table).unwrap();
+                encoder.finish().unwrap()
+            } else {
+                table.to_vec()
+            };
+            directory.extend_from_slice(*tag);
+            directory.extend_from_slice(&((offset + data.len()) as u32).to_be_bytes());
+            directory.extend_from_slice(&(stored.len() as u32).to_be_bytes());
+            directory.extend_from_slice(&(table.len() as u32).to_be_bytes());
+            directory.extend_from_slice(&checksum(table).to_be_bytes());
+            data.extend_from_slice(&stored);
+            data.resize(data.len().next_multiple_of(4), 0);
+        }
+
+        let mut out = Vec::new();
+        out.extend_from_slice(WOFF_SIGNATURE);
+        out.extend_from_slice(&0x00010000u32.to_be_bytes());
+        out.extend_from_slice(&((offset + data.len()) as u32).to_be_bytes());
+        out.extend_from_slice(&(tables.len() as u16).to_be_bytes());
+        out.extend_from_slice(&[0; 2]);
+        out.extend_from_slice(&4096u32.to_be_bytes());
+        out.extend_from_slice(&[0; 24]);
+        out.extend_from_slice(&directory);
+        out.extend_from_slice(&data);
+        out
+    }
+
+    /// Brotli stream made of uncompressed meta-blocks
+    fn brotli_stored(data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut bits = 0u64;
+        let mut num_bits = 0;
+        let mut push = |out: &mut Vec<u8>, value: u64, count: u32, flush: bool| {
+            bits |= value << num_bits;
+            num_bits += count;
+            while num_bits >= 8 || (flush && num_bits > 0) {
+                out.push(bits as u8);
+                bits >>= 8;
+                num_bits = num_bits.saturating_sub(8);
+            }
+        };
+
+        // WBITS 16
+        push(&mut out, 0, 1, false);
+        for chunk in data.chunks(1 << 16) {
+            // ISLAST 0, MNIBBLES 4, MLEN - 1, ISUNCOMPRESSED 1
+            push(&mut out, 0, 1, false);
+            push(&mut out, 0, 2, false);
+            push(&mut out, chunk.len() as u64 - 1, 16, false);
+            push(&mut out, 1, 1, true);
+            out.extend_from_slice(chunk);
+        }
+        // ISLAST 1, ISLASTEMPTY 1
+        push(&mut out, 0b11, 2, true);
+        out
+    }
+
+    /// Table entries are (flags, length, transform length), the stream is stored uncompressed
+    fn woff2(
+        num_tables: u16,
+        entries: &[(u8, u32, Option<u32>)],
+        stream: &[u8],
+        total_sfnt_size: u32,
+    ) -> Vec<u8> {
+        let compressed = brotli_stored(stream);
+
+        let mut directory = Vec::new();
+        for &(flags, length, transform_length) in entries {
+            directory.push(flags);
+            for value in [Some(length), transform_length].into_iter().flatten() {
+                let mut bytes = vec![(value & 0x7F) as u8];
+                let mut rest = value >> 7;
+                while rest != 0 {
+                    bytes.push((rest & 0x7F) as u8 | 0x80);
+                    rest >>= 7;
+                }
+                directory.extend(bytes.iter().rev());
+            }
+        }
+
+        let mut out = Vec::new();
+        out.extend_from_slice(WOFF2_SIGNATURE);
+        out.extend_from_slice(&0x00010000u32.to_be_bytes());
+        out.extend_from_slice(&0u32.to_be_bytes());
+        out.extend_from_slice(&num_tables.to_be_bytes());
+        out.extend_from_slice(&[0; 2]);
+        out.extend_from_slice(&total_sfnt_size.to_be_bytes());
+        out.extend_from_slice(&(compressed.len() as u32).to_be_bytes());
+        out.extend_from_slice(&[0; 24]);
+        out.extend_from_slice(&directory);
+        out.extend_from_slice(&compressed);
+        let length = out.len() as u32;
+        out[8..12].copy_from_slice(&length.to_be_bytes());
+        out
+    }
+
+    /// Three glyphs: empty, a triangle without explicit bbox, and a composite of the triangle
+    fn transformed_glyf() -> Vec<u8> {
+        let n_contours = [0i16, 1, -1].iter().flat_map(|n| n.to_be_bytes()).collect::<Vec<_>>();
+        let n_points = [3];
+        let flags = [127, 127, 125];
+        #[rustfmt::skip]
+        let glyphs = [
+            0, 10, 0, 0,
+            0, 90, 0, 200,
+            0, 100, 0, 200,
+            // no instructions
+            0,
+        ];
+        // ARGS_ARE_XY_VALUES, glyph 1, offset by (5, 5)
+        let composite = [0x00, 0x02, 0x00, 0x01, 5, 5];
+        #[rustfmt::skip]
+        let bbox = [
+            0x20, 0, 0, 0,
+            0, 15, 0, 5, 0, 205, 0, 205,
+        ];
+
+        let streams: [&[u8]; 7] = [&n_contours, &n_points, &flags, &glyphs, &composite, &bbox, &[]];
+
+        let mut glyf = Vec::new();
+        glyf.extend_from_slice(&0u16.to_be_bytes());
+        glyf.extend_from_slice(&0u16.to_be_bytes());
+        glyf.extend_from_slice(&3u16.to_be_bytes());
+        glyf.extend_from_slice(&0u16.to_be_bytes());
+        for stream in streams {
+            glyf.extend_from_slice(&(stream.len() as u32).to_be_bytes());
+        }
+        for stream in streams {
+            glyf.extend_from_slice(stream);
+        }
+        glyf
+    }
+
+    #[derive(Default)]
+    struct Points(Vec<(f32, f32)>);
+
+    impl OutlineBuilder for Points {
+        fn move_to(&mut self, x: f32, y: f32) {
+            self.0.push((x, y));
+        }
+
+        fn line_to(&mut self, x: f32, y: f32) {
+            self.0.push((x, y));
+        }
+
+        fn quad_to(&mut self, _: f32, _: f32, x: f32, y: f32) {
+            self.0.push((x, y));
+        }
+
+        fn curve_to(&mut self, _: f32, _: f32, _: f32, _: f32, x: f32, y: f32) {
+            self.0.push((x, y));
+        }
+
+        fn close(&mut self) {}
+    }
+
+    #[test]
+    fn other_data_is_not_a_web_font() {
+        assert!(decode_web_font(b"").is_none());
+        assert!(decode_web_font(b"wOF").is_none());
+        assert!(decode_web_font(b"\x00\x01\x00\x00").is_none());
+    }
+
+    #[test]
+    fn woff_round_trip() {
+        let head = head(0);
+        let hhea = hhea(0);
+        let maxp = maxp(0);
+        let name = b"some repetitive table data, some repetitive table data".to_vec();
+        let data = woff(&[
+            (b"head", &head, false),
+            (b"hhea", &hhea, true),
+            (b"maxp", &maxp, false),
+            (b"name", &name, true),
+        ]);
+
+        let sfnt = decode_web_font(&data).unwrap().unwrap();
+        let face = ttf_parser::RawFace::parse(&sfnt, 0).unwrap();
+        assert_eq!(face.table(ttf_parser::Tag::from_bytes(b"hhea")), Some(hhea.as_slice()));
+        assert_eq!(face.table(ttf_parser::Tag::from_bytes(b"maxp")), Some(maxp.as_slice()));
+        assert_eq!(face.table(ttf_parser::Tag::from_bytes(b"name")), Some(name.as_slice()));
+        assert_eq!(checksum(&sfnt), 0xB1B0AFBA);
+    }
+
+    #[test]
+    fn woff_malformed() {
+        let head = head(0);
+        let data = woff(&[(b"head", &head, true)]);
+
+        // more tables than the directory holds
+        let mut more_tables = data.clone();
+        more_tables[12..14].copy_from_slice(&u16::MAX.to_be_bytes());
+        assert!(decode_woff(&more_tables).is_err());
+
+        let mut too_large = data.clone();
+        too_large[16..20].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(decode_woff(&too_large).is_err());
+
+        let mut tables_too_large = data.clone();
+        tables_too_large[16..20].copy_from_slice(&10u32.to_be_bytes());
+        assert!(decode_woff(&tables_too_large).is_err());
+
+        let mut out_of_bounds = data.clone();
+        out_of_bounds[48..52].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(decode_woff(&out_of_bounds).is_err());
+
+        let mut wrong_length = data.clone();
+        wrong_length[56..60].copy_from_slice(&53u32.to_be_bytes());
+        assert!(decode_woff(&wrong_length).is_err());
+
+        assert!(decode_woff(&data[..data.len() - 4]).is_err());
+    }
+
+    #[test]
+    fn woff2_transformed_glyf_and_hmtx() {
+        let head = head(0);
+        let hhea = hhea(3);
+        let maxp = maxp(3);
+        let glyf = transformed_glyf();
+        // proportional lsbs are the xMin of each glyph
+        let hmtx = [1u8, 0, 100, 0, 200, 0, 210];
+
+        let stream = [head.as_slice(), &hhea, &maxp, &hmtx, &glyf].concat();
+        let data = woff2(
+            6,
+            &[
+                (1, 54, None),
+                (2, 36, None),
+                (4, 6, None),
+                (3 | 0x40, 12, Some(hmtx.len() as u32)),
+                (10, 0, Some(glyf.len() as u32)),
+                (11, 8, Some(0)),
+            ],
+            &stream,
+            1024,
+        );
+
+        let sfnt = decode_web_font(&data).unwrap().unwrap();
+        assert_eq!(checksum(&sfnt), 0xB1B0AFBA);
+
+        let face = Face::parse(&sfnt, 0).unwrap();
+        assert_eq!(face.number_of_glyphs(), 3);
+
+        assert_eq!(face.glyph_bounding_box(GlyphId(0)), None);
+        assert_eq!(
+            face.glyph_bounding_box(GlyphId(1)),
+            Some(Rect { x_min: 10, y_min: 0, x_max: 200, y_max: 200 })
+        );
+        assert_eq!(
+            face.glyph_bounding_box(GlyphId(2)),
+            Some(Rect { x_min: 15, y_min: 5, x_max: 205, y_max: 205 })
+        );
+
+        let mut points = Points::default();
+        face.outline_glyph(GlyphId(1), &mut points);
+        assert_eq!(points.0, [(10.0, 0.0), (100.0, 200.0), (200.0, 0.0), (10.0, 0.0)]);
+
+        let mut points = Points::default();
+        face.outline_glyph(GlyphId(2), &mut points);
+        assert_eq!(points.0, [(15.0, 5.0), (105.0, 205.0), (205.0, 5.0), (15.0, 5.0)]);
+
+        let metrics = (0..3).map(|idx| {
+            (face.glyph_hor_advance(GlyphId(idx)), face.glyph_hor_side_bearing(GlyphId(idx)))
+        });
+        assert!(metrics.eq([(Some(100), Some(0)), (Some(200), Some(10)), (Some(210), Some(15))]));
+    }
+
+    #[test]
+    fn woff2_without_tables() {
+        let data = woff2(0, &[], &[], 0);
+        let sfnt = decode_web_font(&data).unwrap().unwrap();
+        assert_eq!(sfnt, [0, 1, 0, 0, 0, 0, 0, 16, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn woff2_malformed() {
+        let head = head(0);
+        let entries = [(1, 54, None)];
+
+        assert!(decode_woff2(&woff2(1, &entries, &head, u32::MAX)).is_err());
+        // tables larger than the declared font size
+        assert!(decode_woff2(&woff2(1, &entries, &head, 53)).is_err());
+        // more tables than the directory holds
+        assert!(decode_woff2(&woff2(u16::MAX, &entries, &head, 1024)).is_err());
+        // stream shorter than the tables
+        assert!(decode_woff2(&woff2(1, &entries, &head[..50], 1024)).is_err());
+        // compressed data cut short
+        let data = woff2(1, &entries, &head, 1024);
+        assert!(decode_woff2(&data[..data.len() - 8]).is_err());
+
+        // glyf streams larger than the table
+        let mut glyf = transformed_glyf();
+        glyf[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(reconstruct_glyf(&glyf).is_err());
+
+        // composite glyphs need a bbox
+        let mut glyf = transformed_glyf();
+        let bbox_bitmap = glyf.len() - 12;
+        glyf[bbox_bitmap] = 0;
+        assert!(reconstruct_glyf(&glyf).is_err());
+
+        // more glyphs than contour counts
+        let mut glyf = transformed_glyf();
+        glyf[4..6].copy_from_slice(&4u16.to_be_bytes());
+        assert!(reconstruct_glyf(&glyf).is_err());
+
+        // metrics of more glyphs than glyf has
+        assert!(reconstruct_hmtx(&[3, 0, 100, 0, 100], 2, &[0]).is_err());
+    }
+
+    #[test]
+    fn triplets() {
+        let decode = |flag, bytes: &[u8]| decode_triplet(flag, &mut Reader::new(bytes)).unwrap();
+        assert_eq!(decode(0, &[7]), (0, -7));
+        assert_eq!(decode(3, &[7]), (0, 263));
+        assert_eq!(decode(10, &[7]), (-7, 0));
+        assert_eq!(decode(13, &[7]), (263, 0));
+        assert_eq!(decode(20, &[0x12]), (-2, -3));
+        assert_eq!(decode(23, &[0x12]), (2, 3));
+        assert_eq!(decode(84, &[1, 2]), (-2, -3));
+        assert_eq!(decode(120, &[0x12, 0x34, 0x56]), (-0x123, -0x456));
+        assert_eq!(decode(127, &[1, 2, 3, 4]), (0x102, 0x304));
+        assert!(decode_triplet(124, &mut Reader::new(&[1, 2, 3])).is_err());
+    }
+
+    #[test]
+    fn variable_length_integers() {
+        let base128 = |bytes: &[u8]| Reader::new(bytes).base128();
+        assert_eq!(base128(&[0x3F]).unwrap(), 63);
+        assert_eq!(base128(&[0x81, 0x00]).unwrap(), 128);
+        assert_eq!(base128(&[0x8F, 0xFF, 0xFF, 0xFF, 0x7F]).unwrap(), u32::MAX);
+        assert!(base128(&[0x80, 0x01]).is_err());
+        assert!(base128(&[0x90, 0x80, 0x80, 0x80, 0x00]).is_err());
+        assert!(base128(&[0x81, 0x81, 0x81, 0x81, 0x81, 0x01]).is_err());
+        assert!(base128(&[0x81]).is_err());
+
+        let uint255 = |bytes: &[u8]| Reader::new(bytes).uint255().unwrap();
+        assert_eq!(uint255(&[252]), 252);
+        assert_eq!(uint255(&[255, 0]), 253);
+        assert_eq!(uint255(&[254, 0]), 506);
+        assert_eq!(uint255(&[253, 0x12, 0x34]), 0x1234);
+    }
+}
@@ -1,5 +1,7 @@
 mod app;
+mod archive;
 mod font;
+mod matroska;
 mod ssa;
 mod system;
 mod utils;
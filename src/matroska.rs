@@ -0,0 +1,666 @@
+// a minimal Matroska reader for subtitle tracks and attachments
+// https://www.matroska.org/technical/elements.html
+// https://www.matroska.org/technical/subtitles.html
+
+mod ebml;
+mod mux;
+
+use std::collections::HashMap;
+use std::fs::{File, metadata};
+use std::io::{BufWriter, Read};
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use flate2::read::ZlibDecoder;
+use memmap2::Mmap;
+use tempfile::NamedTempFile;
+
+use self::ebml::{Element, children, read_uint, read_vint};
+use crate::utils::is_font_data;
+
+const EBML_HEADER: u32 = 0x1A45_DFA3;
+const SEGMENT: u32 = 0x1853_8067;
+
+const INFO: u32 = 0x1549_A966;
+const TIMESTAMP_SCALE: u32 = 0x2A_D7B1;
+
+const TRACKS: u32 = 0x1654_AE6B;
+const TRACK_ENTRY: u32 = 0xAE;
+const TRACK_NUMBER: u32 = 0xD7;
+const CODEC_ID: u32 = 0x86;
+const CODEC_PRIVATE: u32 = 0x63A2;
+const CONTENT_ENCODINGS: u32 = 0x6D80;
+const CONTENT_ENCODING: u32 = 0x6240;
+const CONTENT_ENCODING_ORDER: u32 = 0x5031;
+const CONTENT_ENCODING_SCOPE: u32 = 0x5032;
+const CONTENT_ENCODING_TYPE: u32 = 0x5033;
+const CONTENT_COMPRESSION: u32 = 0x5034;
+const CONTENT_COMP_ALGO: u32 = 0x4254;
+const CONTENT_COMP_SETTINGS: u32 = 0x4255;
+
+const CLUSTER: u32 = 0x1F43_B675;
+const CLUSTER_TIMESTAMP: u32 = 0xE7;
+const SIMPLE_BLOCK: u32 = 0xA3;
+const BLOCK_GROUP: u32 = 0xA0;
+const BLOCK: u32 = 0xA1;
+const BLOCK_DURATION: u32 = 0x9B;
+
+const ATTACHMENTS: u32 = 0x1941_A469;
+const ATTACHED_FILE: u32 = 0x61A7;
+const FILE_NAME: u32 = 0x466E;
+const FILE_MIME_TYPE: u32 = 0x4660;
+const FILE_DATA: u32 = 0x465C;
+
+// MIME types of font attachments, as written by mkvmerge and others
+const FONT_MIME_TYPES: [&str; 5] = [
+    "application/x-truetype-font", "application/x-font-ttf", "application/x-font-otf",
+    "application/vnd.ms-opentype", "application/font-sfnt",
+];
+
+// appended when CodecPrivate has no `[Events]` section
+const EVENTS_HEADER: &str =
+    "[Events]\nFormat: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n";
+
+pub struct Matroska {
+    pub subtitles: Vec<SubtitleTrack>,
+    pub attachments: Vec<Attachment>,
+}
+
+/// An (A)SSA track turned back into a script
+pub struct SubtitleTrack {
+    pub number: u64,
+    pub content: String,
+}
+
+pub struct Attachment {
+    pub name: String,
+    pub mime_type: String,
+    pub data: Vec<u8>,
+}
+
+impl Attachment {
+    /// A font to be attached, with the MIME type of its format
+    pub fn font(name: String, data: Vec<u8>) -> Self {
+        let mime_type = match data.get(..4) {
+            Some(b"OTTO") => "font/otf",
+            Some(b"ttcf") => "font/collection",
+            _ => "font/ttf",
+        };
+
+        Self { name, mime_type: mime_type.to_owned(), data }
+    }
+
+    /// By MIME type, or by content since attachments are often mislabeled
+    pub fn is_font(&self) -> bool {
+        let mime_type = self.mime_type.to_ascii_lowercase();
+        mime_type.starts_with("font/")
+            || FONT_MIME_TYPES.contains(&mime_type.as_str())
+            || is_font_data(&self.data)
+    }
+}
+
+/// Read (A)SSA tracks and attachments
+pub fn read(path: &Path) -> Result<Matroska> {
+    read_file(path, true)
+}
+
+/// Read attachments only, without going through the clusters
+pub fn read_attachments(path: &Path) -> Result<Vec<Attachment>> {
+    Ok(read_file(path, false)?.attachments)
+}
+
+/// Write a copy of the file with the attachments added, existing ones are kept,
+/// `dst` is only replaced once the copy is completely written
+pub fn add_attachments(src: &Path, dst: &Path, attachments: &[Attachment]) -> Result<()> {
+    let mapped = map_file(src)?;
+
+    // removed when dropped, if anything goes wrong
+    let dir = dst.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let temp = NamedTempFile::new_in(dir)
+        .with_context(|| format!("Error writing file \"{}\"", dst.display()))?;
+
+    // temporary files are only accessible to the owner
+    if let Ok(meta) = metadata(src) {
+        let _ = temp.as_file().set_permissions(meta.permissions());
+    }
+
+    mux::write(&mapped, attachments, &mut BufWriter::new(temp.as_file()))
+        .with_context(|| format!("Error writing file \"{}\"", dst.display()))?;
+
+    temp.persist(dst).with_context(|| format!("Error writing file \"{}\"", dst.display()))?;
+
+    Ok(())
+}
+
+fn read_file(path: &Path, with_subtitles: bool) -> Result<Matroska> {
+    let mapped = map_file(path)?;
+
+    parse(&mapped, with_subtitles)
+        .with_context(|| format!("Error parsing Matroska file \"{}\"", path.display()))
+}
+
+fn map_file(path: &Path) -> Result<Mmap> {
+    let file =
+        File::open(path).with_context(|| format!("Error reading file \"{}\"", path.display()))?;
+    // memmap so we don't have to read the whole video
+    unsafe { Mmap::map(&file) }
+        .with_context(|| format!("Error reading file \"{}\"", path.display()))
+}
+
+// what is needed to turn blocks of a track back into dialogue lines
+struct TrackInfo {
+    number: u64,
+    header: String,
+    compression: Vec<Compression>,
+    // (ReadOrder, line)
+    events: Vec<(u64, String)>,
+}
+
+#[derive(Clone)]
+enum Compression {
+    Zlib,
+    /// Bytes removed from the start of every frame
+    HeaderStripping(Vec<u8>),
+}
+
+fn parse(data: &[u8], with_subtitles: bool) -> Result<Matroska> {
+    let Some(header) = Element::read(data, 0).filter(|element| element.id == EBML_HEADER) else {
+        bail!("Not a Matroska file");
+    };
+
+    let Some(segment) = Element::read(data, header.end).filter(|element| element.id == SEGMENT)
+    else {
+        bail!("No segment");
+    };
+
+    let mut timestamp_scale = 1_000_000;
+    let mut tracks: HashMap<u64, TrackInfo> = HashMap::new();
+    let mut attachments = Vec::new();
+
+    // top level elements other than clusters
+    walk_segment(data, &segment, false, &mut |element| {
+        let body = element.body(data);
+        match element.id {
+            INFO => {
+                if let Some(scale) = children(body).find(|child| child.id == TIMESTAMP_SCALE) {
+                    timestamp_scale = read_uint(scale.body(body));
+                }
+            }
+            TRACKS if with_subtitles => {
+                for entry in children(body).filter(|child| child.id == TRACK_ENTRY) {
+                    if let Some(track) = read_track(entry.body(body)) {
+                        tracks.insert(track.number, track);
+                    }
+                }
+            }
+            ATTACHMENTS => {
+                attachments.extend(
+                    children(body)
+                        .filter(|child| child.id == ATTACHED_FILE)
+                        .map(|file| read_attachment(file.body(body))),
+                );
+            }
+            _ => {}
+        }
+    });
+
+    if !tracks.is_empty() {
+        let mut cluster_timestamp = 0;
+        let mut unsupported_blocks = 0;
+
+        walk_segment(data, &segment, true, &mut |element| {
+            let body = element.body(data);
+            let (block, duration) = match element.id {
+                CLUSTER_TIMESTAMP => {
+                    cluster_timestamp = read_uint(body);
+                    return;
+                }
+                SIMPLE_BLOCK => (body, None),
+                BLOCK_GROUP => {
+                    let Some(block) = children(body).find(|child| child.id == BLOCK) else {
+                        return;
+                    };
+                    let duration = children(body)
+                        .find(|child| child.id == BLOCK_DURATION)
+                        .map(|child| read_uint(child.body(body)));
+                    (block.body(body), duration)
+                }
+                _ => return,
+            };
+
+            let Some((track_number, len)) = read_vint(block, 0, false) else {
+                return;
+            };
+            let Some(track) = tracks.get_mut(&track_number) else {
+                return;
+            };
+            let Some(&[time_hi, time_lo, flags]) = block.get(len..len + 3) else {
+                return;
+            };
+
+            // subtitles are never laced by muxers
+            if flags & 0x06 != 0 {
+                unsupported_blocks += 1;
+                return;
+            }
+
+            let Some(payload) = decompress(&block[len + 3..], &track.compression) else {
+                unsupported_blocks += 1;
+                return;
+            };
+
+            // saturated for corrupted files, the times are only shown
+            let start = cluster_timestamp
+                .saturating_add_signed(i16::from_be_bytes([time_hi, time_lo]).into())
+                .saturating_mul(timestamp_scale);
+            let end = start.saturating_add(duration.unwrap_or(0).saturating_mul(timestamp_scale));
+
+            if let Some(event) = to_dialogue(&String::from_utf8_lossy(&payload), start, end) {
+                track.events.push(event);
+            }
+        });
+
+        if unsupported_blocks > 0 {
+            eprintln!("{} subtitle blocks are laced or corrupted, ignoring", unsupported_blocks);
+        }
+    }
+
+    let mut subtitles: Vec<_> = tracks
+        .into_values()
+        .map(|mut track| {
+            track.events.sort_by_key(|(read_order, _)| *read_order);
+
+            let mut content = track.header;
+            if !content.ends_with('\n') {
+                content.push('\n');
+            }
+            if !content.to_ascii_lowercase().contains("[events]") {
+                content.push_str(EVENTS_HEADER);
+            }
+            for (_, line) in track.events {
+                content.push_str(&line);
+                content.push('\n');
+            }
+
+            SubtitleTrack { number: track.number, content }
+        })
+        .collect();
+    subtitles.sort_by_key(|track| track.number);
+
+    Ok(Matroska { subtitles, attachments })
+}
+
+// calls `f` with top level elements of the segment, and with children of clusters
+// instead of the clusters themselves if `enter_clusters` is set,
+// clusters of unknown size are always entered as their end is only known by parsing
+fn walk_segment(
+    data: &[u8],
+    segment: &Element,
+    enter_clusters: bool,
+    f: &mut impl FnMut(&Element),
+) {
+    let mut pos = segment.body_start;
+
+    while pos < segment.end {
+        let Some(element) = Element::read(data, pos) else {
+            eprintln!("Corrupted data at offset {}, ignoring the rest", pos);
+            break;
+        };
+
+        if element.id == CLUSTER && (enter_clusters || element.is_unknown_size) {
+            pos = element.body_start;
+            continue;
+        }
+
+        f(&element);
+        pos = element.end;
+    }
+}
+
+fn read_track(entry: &[u8]) -> Option<TrackInfo> {
+    let mut number = None;
+    let mut codec_id = "";
+    let mut codec_private: &[u8] = &[];
+    let mut compression = Vec::new();
+    let mut private_compression = Vec::new();
+
+    for child in children(entry) {
+        let body = child.body(entry);
+        match child.id {
+            TRACK_NUMBER => number = Some(read_uint(body)),
+            CODEC_ID => codec_id = std::str::from_utf8(body).unwrap_or_default(),
+            CODEC_PRIVATE => codec_private = body,
+            CONTENT_ENCODINGS => {
+                // applied in reverse of the order they are decoded
+                let mut encodings: Vec<_> = children(body)
+                    .filter(|encoding| encoding.id == CONTENT_ENCODING)
+                    .map(|encoding| read_content_encoding(encoding.body(body)))
+                    .collect::<Option<_>>()?;
+                encodings.sort_by_key(|(order, _, _)| std::cmp::Reverse(*order));
+
+                for (_, scope, method) in encodings {
+                    if scope & 1 != 0 {
+                        compression.push(method.clone());
+                    }
+                    if scope & 2 != 0 {
+                        private_compression.push(method);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let codec_id = codec_id.trim_end_matches('\0');
+    if !matches!(codec_id, "S_TEXT/ASS" | "S_TEXT/SSA" | "S_ASS" | "S_SSA") {
+        return None;
+    }
+
+    let number = number?;
+    let Some(header) = decompress(codec_private, &private_compression) else {
+        eprintln!("Unsupported header of subtitle track {}, ignoring", number);
+        return None;
+    };
+
+    Some(TrackInfo {
+        number,
+        header: String::from_utf8_lossy(&header).into_owned(),
+        compression,
+        events: Vec::new(),
+    })
+}
+
+// (order, scope, compression), `None` for encryption and unsupported compression
+fn read_content_encoding(encoding: &[u8]) -> Option<(u64, u64, Compression)> {
+    let mut order = 0;
+    let mut scope = 1;
+    let mut encoding_type = 0;
+    let mut algo = 0;
+    let mut settings: &[u8] = &[];
+
+    for child in children(encoding) {
+        let body = child.body(encoding);
+        match child.id {
+            CONTENT_ENCODING_ORDER => order = read_uint(body),
+            CONTENT_ENCODING_SCOPE => scope = read_uint(body),
+            CONTENT_ENCODING_TYPE => encoding_type = read_uint(body),
+            CONTENT_COMPRESSION => {
+                for setting in children(body) {
+                    match setting.id {
+                        CONTENT_COMP_ALGO => algo = read_uint(setting.body(body)),
+                        CONTENT_COMP_SETTINGS => settings = setting.body(body),
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let compression = match (encoding_type, algo) {
+        (0, 0) => Compression::Zlib,
+        (0, 3) => Compression::HeaderStripping(settings.to_owned()),
+        (0, _) => {
+            eprintln!("Unsupported compression of subtitle track (algorithm {}), ignoring", algo);
+            return None;
+        }
+        _ => {
+            eprintln!("Encrypted subtitle track, ignoring");
+            return None;
+        }
+    };
+
+    Some((order, scope, compression))
+}
+
+fn decompress(data: &[u8], compression: &[Compression]) -> Option<Vec<u8>> {
+    let mut data = data.to_owned();
+
+    for method in compression {
+        data = match method {
+            Compression::Zlib => {
+                let mut decoded = Vec::new();
+                ZlibDecoder::new(data.as_slice()).read_to_end(&mut decoded).ok()?;
+                decoded
+            }
+            Compression::HeaderStripping(header) => [header.as_slice(), &data].concat(),
+        };
+    }
+
+    Some(data)
+}
+
+// a block is `ReadOrder, Layer, Style, Name, MarginL, MarginR, MarginV, Effect, Text`,
+// the timing is taken out to the block itself
+fn to_dialogue(payload: &str, start: u64, end: u64) -> Option<(u64, String)> {
+    let (read_order, rest) = payload.split_once(',')?;
+    let (layer, rest) = rest.split_once(',')?;
+
+    let line = format!(
+        "Dialogue: {},{},{},{}",
+        layer.trim(),
+        format_time(start),
+        format_time(end),
+        rest.trim_end_matches(['\r', '\n', '\0'])
+    );
+
+    Some((read_order.trim().parse().unwrap_or(u64::MAX), line))
+}
+
+// `h:mm:ss.cc` from nanoseconds
+fn format_time(ns: u64) -> String {
+    let cs = ns / 10_000_000;
+    format!("{}:{:02}:{:02}.{:02}", cs / 360_000, cs / 6000 % 60, cs / 100 % 60, cs % 100)
+}
+
+fn read_attachment(file: &[u8]) -> Attachment {
+    let mut attachment =
+        Attachment { name: String::new(), mime_type: String::new(), data: Vec::new() };
+
+    for child in children(file) {
+        let body = child.body(file);
+        match child.id {
+            FILE_NAME => attachment.name = String::from_utf8_lossy(body).into_owned(),
+            FILE_MIME_TYPE => attachment.mime_type = String::from_utf8_lossy(body).into_owned(),
+            FILE_DATA => attachment.data = body.to_owned(),
+            _ => {}
+        }
+    }
+
+    attachment
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::ZlibEncoder;
+
+    use super::ebml::write_element;
+    use super::*;
+
+    pub const HEADER: &str = concat!(
+        "[Script Info]\nScriptType: v4.00+\n\n",
+        "[V4+ Styles]\nFormat: Name, Fontname, Fontsize\nStyle: Default,Arial,20\n\n",
+        "[Events]\n",
+        "Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text\n",
+    );
+
+    pub fn element(id: u32, children: &[Vec<u8>]) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_element(&mut out, id, &children.concat());
+        out
+    }
+
+    pub fn uint(id: u32, value: u64) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_element(&mut out, id, &value.to_be_bytes());
+        out
+    }
+
+    pub fn bytes(id: u32, body: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_element(&mut out, id, body);
+        out
+    }
+
+    pub fn file(segment: &[Vec<u8>]) -> Vec<u8> {
+        [element(EBML_HEADER, &[bytes(0x4282, b"matroska")]), element(SEGMENT, segment)].concat()
+    }
+
+    fn zlib(data: &[u8]) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn block(track: u8, time: i16, payload: &[u8]) -> Vec<u8> {
+        [[0x80 | track].as_slice(), &time.to_be_bytes(), &[0], payload].concat()
+    }
+
+    fn track(number: u64, codec_id: &str, children: &[Vec<u8>]) -> Vec<u8> {
+        let common = [uint(TRACK_NUMBER, number), bytes(CODEC_ID, codec_id.as_bytes())];
+        element(TRACK_ENTRY, &[common.as_slice(), children].concat())
+    }
+
+    fn zlib_encoding(scope: u64) -> Vec<u8> {
+        element(CONTENT_ENCODINGS, &[element(CONTENT_ENCODING, &[
+            uint(CONTENT_ENCODING_SCOPE, scope),
+            element(CONTENT_COMPRESSION, &[uint(CONTENT_COMP_ALGO, 0)]),
+        ])])
+    }
+
+    pub fn font_attachment(name: &str, data: &[u8]) -> Vec<u8> {
+        element(ATTACHED_FILE, &[
+            bytes(FILE_NAME, name.as_bytes()),
+            bytes(FILE_MIME_TYPE, b"application/x-truetype-font"),
+            bytes(FILE_DATA, data),
+        ])
+    }
+
+    /// A zlib compressed ASS track
+    pub fn sample_tracks() -> Vec<u8> {
+        element(TRACKS, &[track(1, "S_TEXT/ASS", &[
+            zlib_encoding(3),
+            bytes(CODEC_PRIVATE, &zlib(HEADER.as_bytes())),
+        ])])
+    }
+
+    /// Events of the sample track, out of order
+    pub fn sample_cluster() -> Vec<u8> {
+        element(CLUSTER, &[
+            uint(CLUSTER_TIMESTAMP, 1000),
+            element(BLOCK_GROUP, &[
+                bytes(BLOCK, &block(1, 0, &zlib(b"1,0,Default,,0,0,0,,Second"))),
+                uint(BLOCK_DURATION, 2000),
+            ]),
+            bytes(SIMPLE_BLOCK, &block(1, 500, &zlib(b"0,0,Default,,0,0,0,,First"))),
+        ])
+    }
+
+    pub const SAMPLE_CONTENT: &str = concat!(
+        "Dialogue: 0,0:00:01.50,0:00:01.50,Default,,0,0,0,,First\n",
+        "Dialogue: 0,0:00:01.00,0:00:03.00,Default,,0,0,0,,Second\n",
+    );
+
+    #[test]
+    fn read_subtitles() {
+        let data = file(&[sample_tracks(), sample_cluster()]);
+        let matroska = parse(&data, true).unwrap();
+        assert!(matroska.attachments.is_empty());
+        assert_eq!(matroska.subtitles.len(), 1);
+        assert_eq!(matroska.subtitles[0].number, 1);
+        assert_eq!(matroska.subtitles[0].content, format!("{}{}", HEADER, SAMPLE_CONTENT));
+
+        let matroska = parse(&data, false).unwrap();
+        assert!(matroska.subtitles.is_empty());
+    }
+
+    #[test]
+    fn read_header_stripped_tracks_in_unknown_size_clusters() {
+        let tracks = element(TRACKS, &[
+            track(2, "S_TEXT/SSA", &[
+                element(CONTENT_ENCODINGS, &[element(CONTENT_ENCODING, &[element(
+                    CONTENT_COMPRESSION,
+                    &[uint(CONTENT_COMP_ALGO, 3), bytes(CONTENT_COMP_SETTINGS, b"0,")],
+                )])]),
+                bytes(CODEC_PRIVATE, b"[Script Info]\r\nScriptType: v4.00"),
+            ]),
+            track(3, "V_MPEG4/ISO/AVC", &[]),
+        ]);
+        // unknown size, ends where the next top level element starts
+        let cluster =
+            [[0x1F, 0x43, 0xB6, 0x75, 0xFF].as_slice(), &uint(CLUSTER_TIMESTAMP, 3_600_000)]
+                .concat();
+        let blocks = [
+            bytes(SIMPLE_BLOCK, &block(2, -1000, b"0,Default,,0,0,0,,Text\r\n")),
+            bytes(SIMPLE_BLOCK, &block(3, 0, b"video")),
+        ];
+        let data = file(&[tracks, cluster, blocks.concat()]);
+
+        let matroska = parse(&data, true).unwrap();
+        assert_eq!(matroska.subtitles.len(), 1);
+        assert_eq!(
+            matroska.subtitles[0].content,
+            format!(
+                "[Script Info]\r\nScriptType: v4.00\n{}Dialogue: \
+                 0,0:59:59.00,0:59:59.00,Default,,0,0,0,,Text\n",
+                EVENTS_HEADER
+            )
+        );
+    }
+
+    #[test]
+    fn read_attachments() {
+        let attachments = element(ATTACHMENTS, &[
+            font_attachment("font.ttf", b"\x00\x01\x00\x00data"),
+            font_attachment("other", b""),
+        ]);
+        let matroska = parse(&file(&[attachments]), false).unwrap();
+
+        let [font, other] = matroska.attachments.as_slice() else {
+            panic!("Expected two attachments");
+        };
+        assert_eq!(font.name, "font.ttf");
+        assert_eq!(font.mime_type, "application/x-truetype-font");
+        assert_eq!(font.data, b"\x00\x01\x00\x00data");
+        assert_eq!(other.name, "other");
+        assert!(other.data.is_empty());
+    }
+
+    #[test]
+    fn malformed_files() {
+        assert!(parse(b"", true).is_err());
+        assert!(parse(&element(SEGMENT, &[]), true).is_err());
+        assert!(parse(&element(EBML_HEADER, &[]), true).is_err());
+
+        // truncated or corrupted data after the tracks is ignored
+        let data = file(&[sample_tracks(), sample_cluster()]);
+        let matroska = parse(&data[..data.len() - 20], true).unwrap();
+        assert_eq!(matroska.subtitles.len(), 1);
+        let mut corrupted = data.clone();
+        let cluster = corrupted.windows(4).position(|id| id == [0x1F, 0x43, 0xB6, 0x75]).unwrap();
+        corrupted[cluster] = 0;
+        let matroska = parse(&corrupted, true).unwrap();
+        assert_eq!(matroska.subtitles[0].content, HEADER);
+
+        // blocks with out of range times and durations
+        let tracks = element(TRACKS, &[track(1, "S_ASS", &[])]);
+        let cluster = element(CLUSTER, &[
+            uint(CLUSTER_TIMESTAMP, u64::MAX),
+            element(BLOCK_GROUP, &[
+                bytes(BLOCK, &block(1, i16::MAX, b"0,0,,Text")),
+                uint(BLOCK_DURATION, u64::MAX),
+            ]),
+        ]);
+        let matroska = parse(&file(&[tracks, cluster]), true).unwrap();
+        assert!(matroska.subtitles[0].content.ends_with(",,Text\n"));
+    }
+
+    #[test]
+    fn times() {
+        assert_eq!(format_time(0), "0:00:00.00");
+        assert_eq!(format_time(3_723_450_000_000), "1:02:03.45");
+        assert_eq!(to_dialogue("x,1,Text, with, commas\0", 0, 0).unwrap().0, u64::MAX);
+    }
+}
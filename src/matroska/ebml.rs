@@ -0,0 +1,206 @@
+// EBML, the binary format Matroska is built upon
+// https://www.rfc-editor.org/rfc/rfc8794
+
+/// Position of an element in its parent data
+pub struct Element {
+    pub id: u32,
+    pub start: usize,
+    pub body_start: usize,
+    /// End of the body, clamped to the data, or the end of data for unknown sizes
+    pub end: usize,
+    pub is_unknown_size: bool,
+}
+
+impl Element {
+    /// Read the element header at `pos`, `None` if it is invalid or truncated
+    pub fn read(data: &[u8], pos: usize) -> Option<Self> {
+        let (id, id_len) = read_vint(data, pos, true)?;
+        // IDs are at most 4 bytes long
+        if id_len > 4 {
+            return None;
+        }
+
+        let (size, size_len) = read_vint(data, pos + id_len, false)?;
+        let body_start = pos + id_len + size_len;
+
+        // all value bits set means unknown size
+        let is_unknown_size = size == (1 << (7 * size_len)) - 1;
+        let end = if is_unknown_size {
+            data.len()
+        } else {
+            usize::try_from(size)
+                .ok()
+                .and_then(|size| body_start.checked_add(size))?
+                .min(data.len())
+        };
+
+        Some(Self { id: id as u32, start: pos, body_start, end, is_unknown_size })
+    }
+
+    pub fn body<'a>(&self, data: &'a [u8]) -> &'a [u8] {
+        &data[self.body_start..self.end]
+    }
+
+    /// The whole element, header included
+    pub fn bytes<'a>(&self, data: &'a [u8]) -> &'a [u8] {
+        &data[self.start..self.end]
+    }
+}
+
+/// Children of a master element body, stops at the first invalid one
+pub fn children(data: &[u8]) -> impl Iterator<Item = Element> + '_ {
+    let mut pos = 0;
+
+    std::iter::from_fn(move || {
+        let element = Element::read(data, pos)?;
+        pos = element.end;
+        Some(element)
+    })
+}
+
+/// Variable length integer at `pos` and its length, with the length marker kept for IDs
+pub fn read_vint(data: &[u8], pos: usize, keep_marker: bool) -> Option<(u64, usize)> {
+    let first = *data.get(pos)?;
+    if first == 0 {
+        return None;
+    }
+
+    let len = first.leading_zeros() as usize + 1;
+    let bytes = data.get(pos..pos + len)?;
+
+    let first =
+        if keep_marker { first } else { first & 0xFF_u8.checked_shr(len as u32).unwrap_or(0) };
+    let value = bytes[1..].iter().fold(u64::from(first), |value, &b| value << 8 | u64::from(b));
+
+    Some((value, len))
+}
+
+/// Big-endian unsigned integer of up to 8 bytes
+pub fn read_uint(data: &[u8]) -> u64 {
+    data.iter().take(8).fold(0, |value, &b| value << 8 | u64::from(b))
+}
+
+/// Append an element with the body
+pub fn write_element(out: &mut Vec<u8>, id: u32, body: &[u8]) {
+    write_header(out, id, body.len() as u64);
+    out.extend_from_slice(body);
+}
+
+/// Append the ID and the size of an element, in their shortest forms
+pub fn write_header(out: &mut Vec<u8>, id: u32, size: u64) {
+    out.extend_from_slice(&id_bytes(id));
+
+    // all value bits set is reserved for unknown size
+    let len = (1..8).find(|len| size < (1 << (7 * len)) - 1).unwrap_or(8);
+    out.extend_from_slice(&(size | 1 << (7 * len)).to_be_bytes()[8 - len..]);
+}
+
+/// IDs keep their length markers, so they are written as they are
+pub fn id_bytes(id: u32) -> Vec<u8> {
+    let len = (32 - id.leading_zeros()).div_ceil(8) as usize;
+    id.to_be_bytes()[4 - len..].to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vints() {
+        assert_eq!(read_vint(&[0x81], 0, false), Some((1, 1)));
+        assert_eq!(read_vint(&[0x40, 0x02], 0, false), Some((2, 2)));
+        assert_eq!(read_vint(&[0x00, 0x1A, 0x45, 0xDF, 0xA3], 1, true), Some((0x1A45_DFA3, 4)));
+        assert_eq!(read_vint(&[0x01, 0, 0, 0, 0, 0, 0, 0x05], 0, false), Some((5, 8)));
+        // no length marker in the first byte
+        assert_eq!(read_vint(&[0x00, 0x81], 0, false), None);
+        assert_eq!(read_vint(&[0x40], 0, false), None);
+        assert_eq!(read_vint(&[], 0, false), None);
+    }
+
+    #[test]
+    fn header_round_trip() {
+        for size in [0, 1, 126, 127, 128, (1 << 14) - 2, (1 << 14) - 1, 1 << 40, (1 << 56) - 2] {
+            let mut data = Vec::new();
+            write_header(&mut data, 0x1A45_DFA3, size);
+            let body_start = data.len();
+
+            let element = Element::read(&data, 0).unwrap();
+            assert_eq!(element.id, 0x1A45_DFA3);
+            assert_eq!(element.body_start, body_start);
+            assert!(!element.is_unknown_size);
+            // clamped to the data
+            assert_eq!(element.end, body_start);
+
+            let (read_size, _) = read_vint(&data, 4, false).unwrap();
+            assert_eq!(read_size, size);
+        }
+
+        let mut data = Vec::new();
+        write_header(&mut data, 0xA3, 126);
+        assert_eq!(data, [0xA3, 0xFE]);
+        data.clear();
+        write_header(&mut data, 0xA3, 127);
+        assert_eq!(data, [0xA3, 0x40, 0x7F]);
+    }
+
+    #[test]
+    fn ids() {
+        for id in [0xA3, 0x4DBB, 0x2A_D7B1, 0x1853_8067] {
+            let mut data = Vec::new();
+            write_element(&mut data, id, b"body");
+            let element = Element::read(&data, 0).unwrap();
+            assert_eq!(element.id, id);
+            assert_eq!(element.body(&data), b"body");
+            assert_eq!(element.bytes(&data), data);
+        }
+
+        assert_eq!(id_bytes(0xA3), [0xA3]);
+        assert_eq!(id_bytes(0x1A45_DFA3), [0x1A, 0x45, 0xDF, 0xA3]);
+    }
+
+    #[test]
+    fn malformed_elements() {
+        // IDs longer than 4 bytes
+        assert!(Element::read(&[0x08, 0, 0, 0, 0, 0x80], 0).is_none());
+        // missing or truncated size
+        assert!(Element::read(&[0xA3], 0).is_none());
+        assert!(Element::read(&[0xA3, 0x40], 0).is_none());
+        assert!(Element::read(&[0xA3, 0x00], 0).is_none());
+
+        // sizes beyond the data are clamped
+        let element = Element::read(&[0xA3, 0x01, 0xFF, 0, 0, 0, 0, 0, 0, 1, 2], 0).unwrap();
+        assert_eq!((element.body_start, element.end), (9, 11));
+        assert!(!element.is_unknown_size);
+    }
+
+    #[test]
+    fn unknown_sizes() {
+        for size in
+            [[0xFF].as_slice(), &[0x7F, 0xFF], &[0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]]
+        {
+            let data = [[0xA3].as_slice(), size, b"body"].concat();
+            let element = Element::read(&data, 0).unwrap();
+            assert!(element.is_unknown_size);
+            assert_eq!(element.body(&data), b"body");
+        }
+    }
+
+    #[test]
+    fn children_stop_at_invalid_data() {
+        let mut data = Vec::new();
+        write_element(&mut data, 0xA3, b"first");
+        write_element(&mut data, 0x4DBB, b"second");
+        data.extend_from_slice(&[0x00, 0x81, 0x00]);
+        write_element(&mut data, 0xA3, b"third");
+
+        let ids: Vec<_> = children(&data).map(|child| child.id).collect();
+        assert_eq!(ids, [0xA3, 0x4DBB]);
+    }
+
+    #[test]
+    fn uints() {
+        assert_eq!(read_uint(&[]), 0);
+        assert_eq!(read_uint(&[0x0F, 0x42, 0x40]), 1_000_000);
+        assert_eq!(read_uint(&[0xFF; 9]), u64::MAX);
+    }
+}
@@ -0,0 +1,464 @@
+// attachments are added by rewriting the segment: other top level elements are copied
+// as they are, and the attachments are put before the first cluster like mkvmerge does,
+// since positions in the segment change, seek heads and cues are rewritten
+
+use std::io::Write;
+
+use anyhow::{Result, bail};
+use xxhash_rust::xxh3::xxh3_64;
+
+use super::ebml::{Element, children, id_bytes, read_uint, write_element, write_header};
+use super::{
+    ATTACHED_FILE, ATTACHMENTS, Attachment, CLUSTER, EBML_HEADER, FILE_DATA, FILE_MIME_TYPE,
+    FILE_NAME, SEGMENT,
+};
+
+const SEEK_HEAD: u32 = 0x114D_9B74;
+const SEEK: u32 = 0x4DBB;
+const SEEK_ID: u32 = 0x53AB;
+const SEEK_POSITION: u32 = 0x53AC;
+
+const CUES: u32 = 0x1C53_BB6B;
+const CUE_POINT: u32 = 0xBB;
+const CUE_TRACK_POSITIONS: u32 = 0xB7;
+const CUE_CLUSTER_POSITION: u32 = 0xF1;
+const CUE_CODEC_STATE: u32 = 0xEA;
+
+const FILE_UID: u32 = 0x46AE;
+const CRC_32: u32 = 0xBF;
+
+// a top level element of the segment
+struct Chunk<'a> {
+    id: u32,
+    /// Position in the original segment
+    position: u64,
+    kind: ChunkKind<'a>,
+}
+
+enum ChunkKind<'a> {
+    /// Copied as it is
+    Raw(&'a [u8]),
+    /// Seek heads and cues, rewritten with updated positions
+    Index(&'a [u8]),
+    /// Merged into the new attachments
+    Attachments(&'a [u8]),
+}
+
+pub fn write(data: &[u8], attachments: &[Attachment], out: &mut impl Write) -> Result<()> {
+    let Some(header) = Element::read(data, 0).filter(|element| element.id == EBML_HEADER) else {
+        bail!("Not a Matroska file");
+    };
+
+    let Some(segment) = Element::read(data, header.end).filter(|element| element.id == SEGMENT)
+    else {
+        bail!("No segment");
+    };
+
+    let chunks = split_segment(data, &segment);
+
+    // files already attached are kept as they are
+    let mut files = Vec::new();
+    for chunk in &chunks {
+        if let ChunkKind::Attachments(body) = chunk.kind {
+            files.extend(
+                children(body)
+                    .filter(|child| child.id == ATTACHED_FILE)
+                    .map(|file| file.bytes(body)),
+            );
+        }
+    }
+
+    let mut attachments_body: Vec<u8> = files.concat();
+    for attachment in attachments {
+        write_element(&mut attachments_body, ATTACHED_FILE, &attached_file(attachment));
+    }
+    let mut attachments_element = Vec::new();
+    write_element(&mut attachments_element, ATTACHMENTS, &attachments_body);
+
+    let insert_at = chunks.iter().position(|chunk| chunk.id == CLUSTER).unwrap_or(chunks.len());
+
+    // the first seek head points to the attachments, if none of them does
+    let has_seek = chunks.iter().any(|chunk| match chunk.kind {
+        ChunkKind::Index(body) if chunk.id == SEEK_HEAD => seeks_attachments(body),
+        _ => false,
+    });
+    let seek_head_to_extend =
+        if has_seek { None } else { chunks.iter().position(|chunk| chunk.id == SEEK_HEAD) };
+
+    // positions are written in 8 bytes, so sizes are known before the layout
+    let rewritten_len = |idx: usize, chunk: &Chunk| match chunk.kind {
+        ChunkKind::Raw(bytes) => bytes.len(),
+        ChunkKind::Index(body) => {
+            let mut body_len = rewrite_index(body, &|_| 0).len();
+            if seek_head_to_extend == Some(idx) {
+                body_len += seek_entry(0).len();
+            }
+            let mut header = Vec::new();
+            write_header(&mut header, chunk.id, body_len as u64);
+            header.len() + body_len
+        }
+        ChunkKind::Attachments(_) => 0,
+    };
+
+    // new positions of the chunks, in the same order
+    let mut new_positions = Vec::with_capacity(chunks.len());
+    let mut attachments_position = 0;
+    let mut segment_size = 0;
+    for (idx, chunk) in chunks.iter().enumerate() {
+        if idx == insert_at {
+            attachments_position = segment_size;
+            segment_size += attachments_element.len() as u64;
+        }
+        new_positions.push(segment_size);
+        segment_size += rewritten_len(idx, chunk) as u64;
+    }
+    if insert_at == chunks.len() {
+        attachments_position = segment_size;
+        segment_size += attachments_element.len() as u64;
+    }
+
+    // positions point to top level elements, or into them in corrupted files
+    let map_position = |position: u64| {
+        let idx = chunks.partition_point(|chunk| chunk.position <= position);
+        let Some(chunk) = idx.checked_sub(1).map(|idx| &chunks[idx]) else {
+            return position;
+        };
+        match chunk.kind {
+            ChunkKind::Attachments(_) => attachments_position,
+            _ => new_positions[idx - 1] + (position - chunk.position),
+        }
+    };
+
+    let mut segment_header = Vec::new();
+    write_header(&mut segment_header, SEGMENT, segment_size);
+    out.write_all(header.bytes(data))?;
+    out.write_all(&segment_header)?;
+
+    for (idx, chunk) in chunks.iter().enumerate() {
+        if idx == insert_at {
+            out.write_all(&attachments_element)?;
+        }
+
+        match chunk.kind {
+            ChunkKind::Raw(bytes) => out.write_all(bytes)?,
+            ChunkKind::Index(body) => {
+                let mut rewritten = rewrite_index(body, &map_position);
+                if seek_head_to_extend == Some(idx) {
+                    rewritten.extend(seek_entry(attachments_position));
+                }
+                let mut element = Vec::new();
+                write_element(&mut element, chunk.id, &rewritten);
+                out.write_all(&element)?;
+            }
+            ChunkKind::Attachments(_) => {}
+        }
+    }
+
+    if insert_at == chunks.len() {
+        out.write_all(&attachments_element)?;
+    }
+
+    // anything after a segment of known size
+    if !segment.is_unknown_size {
+        out.write_all(&data[segment.end..])?;
+    }
+
+    out.flush()?;
+
+    Ok(())
+}
+
+// clusters of unknown size are split into their header and children,
+// as their end is only known by parsing
+fn split_segment<'a>(data: &'a [u8], segment: &Element) -> Vec<Chunk<'a>> {
+    let mut chunks = Vec::new();
+    let mut pos = segment.body_start;
+
+    while pos < segment.end {
+        let position = (pos - segment.body_start) as u64;
+
+        let Some(element) = Element::read(data, pos) else {
+            // kept as they are, so nothing is lost
+            chunks.push(Chunk { id: 0, position, kind: ChunkKind::Raw(&data[pos..segment.end]) });
+            break;
+        };
+
+        if element.id == CLUSTER && element.is_unknown_size {
+            let header = &data[pos..element.body_start];
+            chunks.push(Chunk { id: element.id, position, kind: ChunkKind::Raw(header) });
+            pos = element.body_start;
+            continue;
+        }
+
+        let kind = match element.id {
+            ATTACHMENTS => ChunkKind::Attachments(element.body(data)),
+            SEEK_HEAD | CUES => ChunkKind::Index(element.body(data)),
+            _ => ChunkKind::Raw(element.bytes(data)),
+        };
+        chunks.push(Chunk { id: element.id, position, kind });
+        pos = element.end;
+    }
+
+    chunks
+}
+
+// CRC-32 elements are dropped, as they no longer match
+fn rewrite_index(body: &[u8], map_position: &impl Fn(u64) -> u64) -> Vec<u8> {
+    let mut out = Vec::new();
+
+    for child in children(body) {
+        let child_body = child.body(body);
+        match child.id {
+            CRC_32 => {}
+            SEEK | CUE_POINT | CUE_TRACK_POSITIONS => {
+                write_element(&mut out, child.id, &rewrite_index(child_body, map_position));
+            }
+            SEEK_POSITION | CUE_CLUSTER_POSITION | CUE_CODEC_STATE => {
+                // zero codec state means there is none
+                let position = match read_uint(child_body) {
+                    0 if child.id == CUE_CODEC_STATE => 0,
+                    position => map_position(position),
+                };
+                write_element(&mut out, child.id, &position.to_be_bytes());
+            }
+            _ => out.extend_from_slice(child.bytes(body)),
+        }
+    }
+
+    out
+}
+
+fn seeks_attachments(seek_head: &[u8]) -> bool {
+    let attachments_id = id_bytes(ATTACHMENTS);
+
+    children(seek_head).filter(|child| child.id == SEEK).any(|seek| {
+        let seek = seek.body(seek_head);
+        children(seek).any(|child| child.id == SEEK_ID && child.body(seek) == attachments_id)
+    })
+}
+
+fn seek_entry(position: u64) -> Vec<u8> {
+    let mut seek = Vec::new();
+    write_element(&mut seek, SEEK_ID, &id_bytes(ATTACHMENTS));
+    write_element(&mut seek, SEEK_POSITION, &position.to_be_bytes());
+
+    let mut element = Vec::new();
+    write_element(&mut element, SEEK, &seek);
+    element
+}
+
+fn attached_file(attachment: &Attachment) -> Vec<u8> {
+    // derived from the content, so muxing again gives the same file
+    let uid = xxh3_64(&attachment.data).max(1);
+
+    let mut file = Vec::new();
+    write_element(&mut file, FILE_NAME, attachment.name.as_bytes());
+    write_element(&mut file, FILE_MIME_TYPE, attachment.mime_type.as_bytes());
+    write_element(&mut file, FILE_DATA, &attachment.data);
+    write_element(&mut file, FILE_UID, &uid.to_be_bytes());
+    file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::{
+        HEADER, SAMPLE_CONTENT, bytes, element, file, font_attachment, sample_cluster,
+        sample_tracks, uint,
+    };
+    use super::super::{TRACKS, add_attachments, parse, read};
+    use super::*;
+
+    fn seek(id: u32, position: u64) -> Vec<u8> {
+        element(SEEK, &[bytes(SEEK_ID, &id_bytes(id)), uint(SEEK_POSITION, position)])
+    }
+
+    /// Seek head, tracks, cluster and cues, with the positions of the original layout
+    fn sample(seek_attachments: bool) -> Vec<u8> {
+        let tracks = sample_tracks();
+        let cluster = sample_cluster();
+
+        let seek_head = |tracks_position, cues_position| {
+            let mut seeks = vec![seek(TRACKS, tracks_position), seek(CUES, cues_position)];
+            if seek_attachments {
+                seeks.push(seek(ATTACHMENTS, 0));
+            }
+            element(SEEK_HEAD, &seeks)
+        };
+        let tracks_position = seek_head(0, 0).len() as u64;
+        let cluster_position = tracks_position + tracks.len() as u64;
+        let cues_position = cluster_position + cluster.len() as u64;
+
+        let cues = element(CUES, &[element(CUE_POINT, &[element(CUE_TRACK_POSITIONS, &[
+            uint(CUE_CLUSTER_POSITION, cluster_position),
+            uint(CUE_CODEC_STATE, 0),
+        ])])]);
+
+        file(&[seek_head(tracks_position, cues_position), tracks, cluster, cues])
+    }
+
+    /// IDs of the elements each position in seek heads and cues points to
+    fn targets(data: &[u8]) -> Vec<(u32, u32)> {
+        let header = Element::read(data, 0).unwrap();
+        let segment = Element::read(data, header.end).unwrap();
+        let target =
+            |position: u64| Element::read(data, segment.body_start + position as usize).unwrap().id;
+
+        let mut targets = Vec::new();
+        for element in children(segment.body(data)) {
+            let body = element.body(segment.body(data));
+            match element.id {
+                SEEK_HEAD => {
+                    for seek in children(body) {
+                        let seek = seek.body(body);
+                        let mut entries = children(seek);
+                        let id = entries.next().unwrap();
+                        let position = entries.next().unwrap();
+                        targets.push((
+                            read_uint(id.body(seek)) as u32,
+                            target(read_uint(position.body(seek))),
+                        ));
+                    }
+                }
+                CUES => {
+                    let point = children(body).next().unwrap();
+                    let positions = children(point.body(body)).next().unwrap();
+                    let positions = positions.body(point.body(body));
+                    let cluster = children(positions).next().unwrap();
+                    targets.push((CLUSTER, target(read_uint(cluster.body(positions)))));
+                    let codec_state = children(positions).nth(1).unwrap();
+                    assert_eq!(read_uint(codec_state.body(positions)), 0);
+                }
+                _ => {}
+            }
+        }
+        targets
+    }
+
+    #[test]
+    fn mux_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("src.mkv");
+        let dst = dir.path().join("dst.mkv");
+        std::fs::write(&src, sample(false)).unwrap();
+
+        let font = Attachment::font("font.otf".to_owned(), b"OTTOdata".to_vec());
+        add_attachments(&src, &dst, &[font]).unwrap();
+
+        let matroska = read(&dst).unwrap();
+        assert_eq!(matroska.subtitles.len(), 1);
+        assert_eq!(matroska.subtitles[0].content, format!("{}{}", HEADER, SAMPLE_CONTENT));
+        let [font] = matroska.attachments.as_slice() else {
+            panic!("Expected one attachment");
+        };
+        assert_eq!(font.name, "font.otf");
+        assert_eq!(font.mime_type, "font/otf");
+        assert_eq!(font.data, b"OTTOdata");
+
+        // the seek head is extended with the attachments, put before the cluster
+        let data = std::fs::read(&dst).unwrap();
+        assert_eq!(targets(&data), [
+            (TRACKS, TRACKS),
+            (CUES, CUES),
+            (ATTACHMENTS, ATTACHMENTS),
+            (CLUSTER, CLUSTER)
+        ]);
+        let attachments = data.windows(4).position(|id| id == id_bytes(ATTACHMENTS)).unwrap();
+        let cluster = data.windows(4).position(|id| id == id_bytes(CLUSTER)).unwrap();
+        assert!(attachments < cluster);
+
+        // files already attached are kept, and muxing gives the same file every time
+        let font = Attachment::font("font.ttf".to_owned(), b"\x00\x01\x00\x00".to_vec());
+        add_attachments(&dst, &dst, &[font]).unwrap();
+        let names: Vec<_> = read(&dst).unwrap().attachments.into_iter().map(|a| a.name).collect();
+        assert_eq!(names, ["font.otf", "font.ttf"]);
+
+        let again = dir.path().join("again.mkv");
+        let font = Attachment::font("font.otf".to_owned(), b"OTTOdata".to_vec());
+        add_attachments(&src, &again, &[font]).unwrap();
+        assert_eq!(std::fs::read(&again).unwrap(), data);
+    }
+
+    #[test]
+    fn existing_attachments_are_merged() {
+        let attachments = element(ATTACHMENTS, &[font_attachment("old.ttf", b"old")]);
+        let mut data = sample(true);
+        // at the end of the segment, pointed to by the seek head
+        let header = Element::read(&data, 0).unwrap();
+        let segment = Element::read(&data, header.end).unwrap();
+        let position = (segment.end - segment.body_start) as u64;
+        let mut body = [segment.body(&data), &attachments].concat();
+        // the SeekPosition value follows the ID in the seek head
+        let seek_position =
+            body.windows(4).position(|id| id == id_bytes(ATTACHMENTS)).unwrap() + 4 + 3;
+        body[seek_position..seek_position + 8].copy_from_slice(&position.to_be_bytes());
+        data.truncate(header.end);
+        write_element(&mut data, SEGMENT, &body);
+
+        let mut out = Vec::new();
+        let font = Attachment::font("new.ttf".to_owned(), b"\x00\x01\x00\x00".to_vec());
+        write(&data, &[font], &mut out).unwrap();
+
+        let matroska = parse(&out, true).unwrap();
+        let names: Vec<_> = matroska.attachments.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, ["old.ttf", "new.ttf"]);
+        assert_eq!(matroska.subtitles[0].content, format!("{}{}", HEADER, SAMPLE_CONTENT));
+
+        // one attachments element and its ID in the seek head, which now points to it
+        assert_eq!(out.windows(4).filter(|id| *id == id_bytes(ATTACHMENTS)).count(), 2);
+        assert_eq!(targets(&out), [
+            (TRACKS, TRACKS),
+            (CUES, CUES),
+            (ATTACHMENTS, ATTACHMENTS),
+            (CLUSTER, CLUSTER)
+        ]);
+    }
+
+    #[test]
+    fn unknown_sizes_and_trailing_data() {
+        let cluster = sample_cluster();
+        let cluster_body = &cluster[Element::read(&cluster, 0).unwrap().body_start..];
+        let unknown_cluster = [&[0x1F, 0x43, 0xB6, 0x75, 0xFF], cluster_body].concat();
+        let data = file(&[sample_tracks(), unknown_cluster]);
+
+        // a segment of unknown size ends with the file
+        let header = Element::read(&data, 0).unwrap();
+        let segment = Element::read(&data, header.end).unwrap();
+        let unknown_segment =
+            [&data[..header.end], &[0x18, 0x53, 0x80, 0x67, 0xFF], segment.body(&data)].concat();
+
+        for (data, trailing) in [(data, b"trailing".as_slice()), (unknown_segment, b"")] {
+            let data = [data.as_slice(), trailing].concat();
+            let mut out = Vec::new();
+            let font = Attachment::font("font.ttf".to_owned(), b"\x00\x01\x00\x00".to_vec());
+            write(&data, &[font], &mut out).unwrap();
+
+            assert!(out.ends_with(trailing));
+            let matroska = parse(&out, true).unwrap();
+            assert_eq!(matroska.attachments.len(), 1);
+            assert_eq!(matroska.subtitles[0].content, format!("{}{}", HEADER, SAMPLE_CONTENT));
+        }
+    }
+
+    #[test]
+    fn malformed_files() {
+        let mut out = Vec::new();
+        assert!(write(b"", &[], &mut out).is_err());
+        assert!(write(&element(EBML_HEADER, &[]), &[], &mut out).is_err());
+        assert!(out.is_empty());
+
+        // destination is left as it is
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("src.mkv");
+        let dst = dir.path().join("dst.mkv");
+        std::fs::write(&src, b"not a video").unwrap();
+        std::fs::write(&dst, b"old").unwrap();
+        assert!(add_attachments(&src, &dst, &[]).is_err());
+        assert_eq!(std::fs::read(&dst).unwrap(), b"old");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 2);
+
+        // corrupted data in the segment is kept as it is
+        let data = file(&[sample_tracks(), vec![0x00, 0x81, 0x00]]);
+        write(&data, &[], &mut out).unwrap();
+        assert!(out.windows(3).any(|bytes| bytes == [0x00, 0x81, 0x00]));
+    }
+}
@@ -1,35 +1,181 @@
-use std::collections::HashSet;
+mod charset;
+mod embed;
+mod fallback;
+mod markup;
+mod srt;
+mod tags;
+mod ttml;
+mod webvtt;
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fmt::Display;
-use std::fs::{create_dir_all, read_to_string, write};
+use std::fs::{create_dir_all, write};
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use std::sync::LazyLock;
 
 use anyhow::{Context, Result};
 // this crate is very probably using tons of LLM generated code
 // I definitely don't like that, but at least it has fairly nice API
 // and there is not a single crate else that follows basic SSA specs
-use ass_core::{Script, Section, parser::SectionType};
-use regex::Regex;
+use ass_core::parser::{IssueCategory, IssueSeverity, SectionType};
+use ass_core::{Script, Section};
+use encoding_rs::Encoding;
 
-use crate::utils::{is_ssa, walk_dir};
+use self::charset::{decode_text, read_text};
+use self::tags::{Tag, Token, is_visible, text_chars, tokenize};
+use crate::archive::{member_path, read_members};
+use crate::font::{WEIGHT_BOLD, WEIGHT_REGULAR, font_names};
+use crate::matroska;
+use crate::utils::{
+    ext_endswith, has_extension, is_matroska, is_zip, normalize_font_name, walk_dir,
+};
 
 pub struct SsaFonts {
-    fonts: HashSet<String>,
+    /// Keyed by normalized names
+    fonts: HashMap<String, SsaFont>,
+    /// Subtitle files or Matroska tracks read, referred to by `Location`
+    files: Vec<PathBuf>,
+    /// Fonts in `[Fonts]` sections of the subtitles, or attached to Matroska files
+    embedded: Vec<EmbeddedFont>,
+    /// Files and normalized names of fonts embedded in them to indices in `embedded`,
+    /// attachments are embedded in every track of the Matroska file
+    embedded_names: HashMap<(usize, String), usize>,
+    /// Encoding of subtitles without BOM, guessed if not set
+    encoding: Option<&'static Encoding>,
+    /// Whether subtitles in ZIP archives are indexed
+    use_archives: bool,
+}
+
+pub struct SsaFont {
+    /// The spelling first seen
+    pub name: String,
+    /// Every style the font is drawn in, with characters drawn in it
+    pub styles: BTreeMap<FontStyle, DrawnChars>,
+}
+
+/// Lines each character is drawn on
+pub type DrawnChars = BTreeMap<char, Vec<Location>>;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Location {
+    /// Index of the subtitle file, see `SsaFonts::file`
+    pub file: usize,
+    /// 1-based line number
+    pub line: usize,
+}
+
+/// Weight and slant a font is requested in
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FontStyle {
+    pub weight: u16,
+    pub is_italic: bool,
+}
+
+impl FontStyle {
+    pub const REGULAR: Self = Self { weight: WEIGHT_REGULAR, is_italic: false };
+
+    // same as libass, 1 and -1 (from VSFilter style fields) are bold,
+    // other positive values are weights, and anything else is regular
+    fn from_values(bold: i32, italic: i32) -> Self {
+        let weight = match bold {
+            1 | -1 => WEIGHT_BOLD,
+            ..=0 => WEIGHT_REGULAR,
+            weight => weight.min(1000) as u16,
+        };
+
+        Self { weight, is_italic: italic != 0 }
+    }
+}
+
+impl Display for FontStyle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.weight, self.is_italic) {
+            (WEIGHT_REGULAR, false) => write!(f, "Regular"),
+            (WEIGHT_REGULAR, true) => write!(f, "Italic"),
+            (WEIGHT_BOLD, false) => write!(f, "Bold"),
+            (WEIGHT_BOLD, true) => write!(f, "Bold Italic"),
+            (weight, false) => write!(f, "Weight {}", weight),
+            (weight, true) => write!(f, "Weight {} Italic", weight),
+        }
+    }
 }
 
 impl SsaFonts {
     pub fn new() -> Self {
-        Self { fonts: HashSet::new() }
+        Self {
+            fonts: HashMap::new(),
+            files: Vec::new(),
+            embedded: Vec::new(),
+            embedded_names: HashMap::new(),
+            encoding: None,
+            use_archives: false,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fonts.is_empty()
+    }
+
+    /// Returns characters drawn with the font in the style, to be filled in
+    pub fn insert(&mut self, name: String, style: FontStyle) -> &mut DrawnChars {
+        self.fonts
+            .entry(normalize_font_name(&name))
+            .or_insert_with(|| SsaFont { name, styles: BTreeMap::new() })
+            .styles
+            .entry(style)
+            .or_default()
+    }
+
+    pub fn file(&self, idx: usize) -> &Path {
+        &self.files[idx]
+    }
+
+    /// Embedded font providing the name in every file the font is drawn in,
+    /// the one in the first file if they differ
+    pub fn embedded_font(&self, font: &SsaFont) -> Option<&EmbeddedFont> {
+        let key = normalize_font_name(&font.name);
+        let mut files = font.styles.values().flat_map(DrawnChars::values).flatten();
+        let first = self.embedded_in(&key, files.next()?.file)?;
+        files.all(|location| self.embedded_in(&key, location.file).is_some()).then_some(first)
+    }
+
+    /// Characters drawn in files that don't embed a font providing the name
+    pub fn drawn_without_embedded(&self, name: &str, drawn: &DrawnChars) -> DrawnChars {
+        let key = normalize_font_name(name);
+        drawn
+            .iter()
+            .filter_map(|(&c, locations)| {
+                let locations: Vec<_> = locations
+                    .iter()
+                    .copied()
+                    .filter(|location| self.embedded_in(&key, location.file).is_none())
+                    .collect();
+                (!locations.is_empty()).then_some((c, locations))
+            })
+            .collect()
+    }
+
+    fn embedded_in(&self, key: &str, file: usize) -> Option<&EmbeddedFont> {
+        self.embedded_names.get(&(file, key.to_owned())).map(|&idx| &self.embedded[idx])
+    }
+
+    pub fn embedded_fonts(&self) -> &[EmbeddedFont] {
+        &self.embedded
+    }
+
+    /// Encoding of subtitles indexed afterwards, when they have no BOM
+    pub fn force_encoding(&mut self, encoding: Option<&'static Encoding>) {
+        self.encoding = encoding;
     }
 
-    pub fn inner(&self) -> &HashSet<String> {
-        &self.fonts
+    /// Also index subtitles in ZIP archives found afterwards
+    pub fn use_archives(&mut self, use_archives: bool) {
+        self.use_archives = use_archives;
     }
 
-    pub fn load(path: &Path) -> Result<Self> {
-        let content = read_to_string(path)
-            .with_context(|| format!("Error reading file \"{}\"", path.display()))?;
+    pub fn load(path: &Path, encoding: Option<&'static Encoding>) -> Result<Self> {
+        let content = read_text(path, encoding)?;
 
         Ok(content.parse().unwrap())
     }
@@ -46,24 +192,137 @@ impl SsaFonts {
         Ok(())
     }
 
+    /// Index text subtitles and Matroska files, and ZIP archives if enabled
     pub fn index(&mut self, path: &Path, is_recursive: bool) {
-        let mut process = |path: PathBuf| self.fonts.extend(get_ssa_fonts(&path));
+        let use_archives = self.use_archives;
+        let may_index =
+            |path: &Path| is_subtitle(path) || is_matroska(path) || (use_archives && is_zip(path));
+        walk_dir(path, is_recursive, &may_index, &mut |path| self.index_file(path))
+    }
+
+    pub fn index_file(&mut self, path: PathBuf) {
+        if is_matroska(&path) {
+            self.index_matroska(path);
+            return;
+        }
 
-        walk_dir(path, is_recursive, &is_ssa, &mut process)
+        if is_zip(&path) {
+            self.index_archive(path);
+            return;
+        }
+
+        let Some(get_fonts) = font_extractor(&path) else {
+            return;
+        };
+
+        let content = match read_text(&path, self.encoding) {
+            Ok(content) => content,
+            Err(err) => {
+                eprintln!("{:#}", err);
+                return;
+            }
+        };
+
+        self.index_subtitle(path, &content, get_fonts);
     }
 
-    pub fn sorted(&self) -> Vec<String> {
-        let mut vec: Vec<_> = self.fonts.iter().cloned().collect();
-        vec.sort_unstable();
-        vec
+    // each track is indexed as a script, fonts attached count as embedded
+    fn index_matroska(&mut self, path: PathBuf) {
+        let mkv = match matroska::read(&path) {
+            Ok(mkv) => mkv,
+            Err(err) => {
+                eprintln!("{:#}", err);
+                return;
+            }
+        };
+
+        let tracks = self.files.len()..self.files.len() + mkv.subtitles.len();
+        for attachment in mkv.attachments.into_iter().filter(|attachment| attachment.is_font()) {
+            let font = EmbeddedFont { name: attachment.name, data: attachment.data };
+            self.add_embedded(font, &path, tracks.clone());
+        }
+
+        for track in mkv.subtitles {
+            let label = PathBuf::from(format!("{} (track {})", path.display(), track.number));
+            self.index_subtitle(label, &track.content, get_ssa_fonts);
+        }
+    }
+
+    // subtitles are read in memory one at a time, each one is labelled as if the archive were a directory
+    fn index_archive(&mut self, path: PathBuf) {
+        let may_read = |name: &str, _: &[u8]| member_font_extractor(name).is_some();
+        let result = read_members(&path, may_read, |member| {
+            if let Some(get_fonts) = member_font_extractor(&member.name) {
+                let label = member_path(&path, &member.name);
+                let content = decode_text(&member.data, &label, self.encoding);
+                self.index_subtitle(label, &content, get_fonts);
+            }
+            Ok(())
+        });
+
+        if let Err(err) = result {
+            eprintln!("{:#}", err);
+        }
+    }
+
+    fn index_subtitle(&mut self, path: PathBuf, content: &str, get_fonts: FontExtractor) {
+        let file = self.files.len();
+
+        for font in embed::read_fonts(content) {
+            self.add_embedded(font, &path, file..file + 1);
+        }
+
+        for ((name, style), chars) in get_fonts(&path, content) {
+            let drawn = self.insert(name, style);
+            for (c, lines) in chars {
+                let locations = lines.into_iter().map(|line| Location { file, line });
+                drawn.entry(c).or_default().extend(locations);
+            }
+        }
+
+        self.files.push(path);
+    }
+
+    fn add_embedded(&mut self, font: EmbeddedFont, source: &Path, files: Range<usize>) {
+        let names = font_names(&font.data);
+        if names.is_empty() {
+            eprintln!(
+                "Embedded font \"{}\" in \"{}\" is not a valid font, ignoring",
+                font.name,
+                source.display()
+            );
+            return;
+        }
+
+        // the same fonts are often embedded in every episode, they are kept once
+        let idx = match self.embedded.iter().position(|known| known.data == font.data) {
+            Some(idx) => idx,
+            None => {
+                self.embedded.push(font);
+                self.embedded.len() - 1
+            }
+        };
+
+        for file in files {
+            for name in &names {
+                self.embedded_names.entry((file, normalize_font_name(name))).or_insert(idx);
+            }
+        }
+    }
+
+    /// Sorted by normalized names
+    pub fn sorted(&self) -> Vec<&SsaFont> {
+        let mut vec: Vec<_> = self.fonts.iter().collect();
+        vec.sort_unstable_by_key(|(key, _)| *key);
+        vec.into_iter().map(|(_, font)| font).collect()
     }
 }
 
-// simple one entry per line format
+// simple one entry per line format, styles are not kept
 impl Display for SsaFonts {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for str in self.sorted() {
-            writeln!(f, "{str}")?;
+        for font in self.sorted() {
+            writeln!(f, "{}", font.name)?;
         }
 
         Ok(())
@@ -74,68 +333,498 @@ impl FromStr for SsaFonts {
     type Err = std::convert::Infallible;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(Self { fonts: s.lines().map(String::from).collect() })
+        let mut fonts = Self::new();
+        for line in s.lines().filter(|line| !line.trim().is_empty()) {
+            fonts.insert(line.to_owned(), FontStyle::REGULAR);
+        }
+        Ok(fonts)
     }
 }
 
-fn get_ssa_fonts(path: &Path) -> HashSet<String> {
-    // in SSA, "{\fnFont Name}" specifies a font override for following text
-    // multiple style overrides may be specified in a single pair of "{}"
-    // we only match the last specified font name in each "{}" as it would override previous ones
-    // test it out with "Hello, {\fnFoo Font\fs42\fnBar Font}Rust {\fnrustc\fs10\fncargo\b1}World!"
-    // it will capture "Bar Font" and "cargo"
-    #[allow(clippy::unwrap_used, reason = "tested")]
-    static FONT_OVRD_REGEX: LazyLock<Regex> =
-        LazyLock::new(|| Regex::new(r"\{[^{}]*\\fn([^{}\\]+).*?}").unwrap());
+/// A font file to be embedded in a script
+pub struct EmbeddedFont {
+    /// File name in the `fontname:` line
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+/// Write the script with fonts added to its `[Fonts]` section,
+/// returns the number of fonts added, fonts of the same names already there are skipped,
+/// the script is written in UTF-8 whatever its original encoding is,
+/// nothing is written if no font is added
+pub fn embed_fonts(
+    src: &Path,
+    dst: &Path,
+    fonts: &[EmbeddedFont],
+    encoding: Option<&'static Encoding>,
+) -> Result<usize> {
+    let content = read_text(src, encoding)?;
+
+    let (content, added) = embed::insert_fonts(&content, fonts);
+    if added == 0 {
+        return Ok(0);
+    }
+
+    write(dst, content).with_context(|| format!("Error writing file \"{}\"", dst.display()))?;
+
+    Ok(added)
+}
+
+// what text is drawn with, as set by a style and changed by override tags
+#[derive(Clone, Copy)]
+struct DrawState<'a> {
+    font: &'a str,
+    bold: i32,
+    italic: i32,
+}
+
+// fields of a style line that matter, as they are in the script
+struct StyleDef<'a> {
+    name: &'a str,
+    fontname: &'a str,
+    bold: &'a str,
+    italic: &'a str,
+}
+
+// a dialogue line, comments are not kept
+struct DialogueDef<'a> {
+    style: &'a str,
+    text: &'a str,
+    /// 1-based line number
+    line: usize,
+}
+
+// lines each character is drawn on, for each font and style
+type UsedFonts = HashMap<(String, FontStyle), BTreeMap<char, BTreeSet<usize>>>;
+
+type FontExtractor = fn(&Path, &str) -> UsedFonts;
+
+// text subtitle formats by their extensions, fonts in `[Fonts]` sections are read for all of them
+const SUBTITLE_FORMATS: [(&[&str], FontExtractor); 4] = [
+    (&["ssa", "ass"], get_ssa_fonts),
+    (&["srt"], srt::get_fonts),
+    (&["vtt"], webvtt::get_fonts),
+    (&["ttml", "dfxp"], ttml::get_fonts),
+];
+
+/// Text subtitles used fonts can be read from
+pub fn is_subtitle(path: &Path) -> bool {
+    font_extractor(path).is_some()
+}
+
+fn font_extractor(path: &Path) -> Option<FontExtractor> {
+    SUBTITLE_FORMATS
+        .iter()
+        .find(|(extensions, _)| ext_endswith(path, extensions))
+        .map(|&(_, get_fonts)| get_fonts)
+}
 
+// members of archives are not on disk
+fn member_font_extractor(name: &str) -> Option<FontExtractor> {
+    SUBTITLE_FORMATS
+        .iter()
+        .find(|(extensions, _)| has_extension(Path::new(name), extensions))
+        .map(|&(_, get_fonts)| get_fonts)
+}
+
+// plain text drawn with the font on the line, nothing if it draws no glyph
+fn add_text(fonts: &mut UsedFonts, font: &str, style: FontStyle, text: &str, line: usize) {
+    if text.chars().all(char::is_whitespace) {
+        return;
+    }
+
+    let drawn = fonts.entry((font.to_owned(), style)).or_default();
+    for c in text.chars().filter(|c| !c.is_control()) {
+        drawn.entry(c).or_default().insert(line);
+    }
+}
+
+// the first family in a CSS-like list that is not generic, as the one meant to be used
+fn first_family(list: &str, generic_families: &[&str]) -> Option<String> {
+    list.split(',')
+        .map(|family| family.trim().trim_matches(['"', '\'']).trim())
+        .find(|family| {
+            !family.is_empty()
+                && !generic_families.iter().any(|generic| family.eq_ignore_ascii_case(generic))
+        })
+        .map(str::to_owned)
+}
+
+// CSS `font-weight` and TTML `tts:fontWeight`, `normal`, `bold` or a number
+fn parse_weight(value: &str) -> Option<u16> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "normal" => Some(WEIGHT_REGULAR),
+        "bold" => Some(WEIGHT_BOLD),
+        value => value.parse().ok().filter(|weight| (1..=1000).contains(weight)),
+    }
+}
+
+// CSS `font-style` and TTML `tts:fontStyle`, whether it is italic
+fn parse_slant(value: &str) -> Option<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "normal" => Some(false),
+        "italic" | "oblique" => Some(true),
+        _ => None,
+    }
+}
+
+fn get_ssa_fonts(path: &Path, content: &str) -> UsedFonts {
     fn strip_prefix(s: &str) -> String {
         s.strip_prefix('@').unwrap_or(s).to_owned()
     }
 
-    let Ok(content) = read_to_string(path) else {
-        eprintln!("Error reading file \"{}\"", path.display());
-        return HashSet::new();
-    };
+    // style fields are read like `atoi`
+    fn parse_int(s: &str) -> i32 {
+        s.trim().parse().unwrap_or(0)
+    }
 
-    let Ok(sub) = Script::parse(&content) else {
-        eprintln!("Error parsing (A)SSA file \"{}\"", path.display());
-        return HashSet::new();
-    };
+    let (styles, dialogues) = read_script(content).unwrap_or_else(|reason| {
+        eprintln!("{} in \"{}\", reading it leniently", reason, path.display());
+        fallback::read_script(path, content)
+    });
+
+    // a later definition of the same name replaces the earlier one, same as libass
+    let style_states: HashMap<&str, DrawState> = styles
+        .iter()
+        .map(|style| {
+            let state = DrawState {
+                font: style.fontname,
+                bold: parse_int(style.bold),
+                italic: parse_int(style.italic),
+            };
+            (style_key(style.name), state)
+        })
+        .collect();
+
+    // where unknown styles are used, reported once for each of them
+    let mut unknown_styles: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+
+    let fallback_style = style_states
+        .get(DEFAULT_STYLE)
+        .map(|_| DEFAULT_STYLE)
+        .or_else(|| styles.first().map(|style| style_key(style.name)));
+
+    // `WrapStyle` in `[Script Info]`, 2 makes `\n` a line break instead of a space
+    const HARD_WRAP: i32 = 2;
+    let script_wrap_style = content
+        .lines()
+        .find_map(|line| line.trim_start().strip_prefix("WrapStyle:"))
+        .map_or(0, parse_int);
+
+    let lines = LineIndex::new(content);
+    let mut fonts = UsedFonts::new();
+
+    for dialogue in dialogues {
+        let line_style = match style_states.get(style_key(dialogue.style)) {
+            Some(&state) => state,
+            None => {
+                unknown_styles.entry(dialogue.style).or_default().push(dialogue.line);
+                // the font of an unknown style can't be told if there is no style at all,
+                // but overrides still can
+                fallback_style
+                    .and_then(|name| style_states.get(name).copied())
+                    .unwrap_or(DrawState { font: "", bold: 0, italic: 0 })
+            }
+        };
+
+        // tags without values go back to the current style, which is changed by `\r<style>`
+        let mut style = line_style;
+        let mut state = line_style;
+        let mut is_drawing = false;
+        let mut wrap_style = script_wrap_style;
+
+        for token in tokenize(dialogue.text) {
+            match token {
+                Token::Text(text) => {
+                    // drawings are not text
+                    if !is_drawing && !state.font.is_empty() && is_visible(text) {
+                        let font_style = FontStyle::from_values(state.bold, state.italic);
+                        let line = lines.line_of(text).unwrap_or(dialogue.line);
+                        let drawn =
+                            fonts.entry((strip_prefix(state.font), font_style)).or_default();
+                        for c in text_chars(text, wrap_style == HARD_WRAP) {
+                            drawn.entry(c).or_default().insert(line);
+                        }
+                    }
+                }
+                Token::Tag(Tag::FontName(name)) => {
+                    state.font = if name.is_empty() { style.font } else { name };
+                }
+                Token::Tag(Tag::Reset(name)) => {
+                    style = name
+                        .and_then(|name| style_states.get(style_key(name)).copied())
+                        .unwrap_or(line_style);
+                    state = style;
+                }
+                Token::Tag(Tag::Bold(bold)) => state.bold = bold.unwrap_or(style.bold),
+                Token::Tag(Tag::Italic(italic)) => state.italic = italic.unwrap_or(style.italic),
+                Token::Tag(Tag::Drawing(mode)) => is_drawing = mode > 0,
+                Token::Tag(Tag::WrapStyle(value)) => {
+                    wrap_style = value.unwrap_or(script_wrap_style);
+                }
+            }
+        }
+    }
+
+    for (name, lines) in unknown_styles {
+        let lines: Vec<_> = lines.iter().map(usize::to_string).collect();
+        match fallback_style {
+            Some(fallback) => eprintln!(
+                "Unknown style \"{}\" in \"{}\" line {}, using \"{}\" instead",
+                name,
+                path.display(),
+                lines.join(", "),
+                fallback
+            ),
+            None => eprintln!(
+                "Unknown style \"{}\" in \"{}\" line {}",
+                name,
+                path.display(),
+                lines.join(", ")
+            ),
+        }
+    }
+
+    fonts
+}
+
+const DEFAULT_STYLE: &str = "Default";
+
+// style names as libass compares them, leading `*` are dropped
+// and `Default` is case-insensitive, same as VSFilter
+fn style_key(name: &str) -> &str {
+    let name = name.trim_start_matches('*');
+    if name.eq_ignore_ascii_case(DEFAULT_STYLE) { DEFAULT_STYLE } else { name }
+}
+
+// styles and dialogues parsed by `ass_core`, or why it can't be trusted,
+// e.g. lines it drops for having too few fields
+fn read_script(content: &str) -> Result<(Vec<StyleDef<'_>>, Vec<DialogueDef<'_>>), String> {
+    let sub = Script::parse(content).map_err(|err| format!("Error parsing script ({})", err))?;
+
+    // sections it doesn't know, e.g. `[Aegisub Project Garbage]`, are warned about too
+    let read_sections = read_section_lines(content);
+    if sub.issues().iter().any(|issue| {
+        let is_problem = match issue.severity {
+            IssueSeverity::Info => false,
+            IssueSeverity::Warning => issue.category != IssueCategory::Structure,
+            IssueSeverity::Error | IssueSeverity::Critical => true,
+        };
+        is_problem && read_sections.iter().any(|lines| lines.contains(&issue.line))
+    }) {
+        return Err("Malformed lines".to_owned());
+    }
 
     let Some(Section::Styles(styles)) = sub.find_section(SectionType::Styles) else {
-        eprintln!("The script does not contain styles section: \"{}\"", path.display());
-        return HashSet::new();
+        return Err("No styles section".to_owned());
     };
 
+    // `*` in front of a style name is nothing for renderers, not inheritance
+    if styles.iter().any(|style| style.parent.is_some()) {
+        return Err("Style names starting with \"*\"".to_owned());
+    }
+
     let Some(Section::Events(events)) = sub.find_section(SectionType::Events) else {
-        eprintln!("The script does not contain events section: \"{}\"", path.display());
-        return HashSet::new();
+        return Err("No events section".to_owned());
     };
 
-    let mut fonts = HashSet::new();
-    let mut used_styles = HashSet::new();
+    let styles = styles
+        .iter()
+        .map(|style| StyleDef {
+            name: style.name,
+            fontname: style.fontname,
+            bold: style.bold,
+            italic: style.italic,
+        })
+        .collect();
 
-    events.iter().filter(|event| event.is_dialogue()).for_each(|dialogue| {
-        // add dialogue style font if text does not start with an override
-        if !FONT_OVRD_REGEX.is_match_at(dialogue.text, 0) {
-            used_styles.insert(dialogue.style);
+    let dialogues = events
+        .iter()
+        .filter(|event| event.is_dialogue())
+        .map(|event| DialogueDef {
+            style: event.style,
+            text: event.text,
+            line: event.span.line as usize,
+        })
+        .collect();
+
+    Ok((styles, dialogues))
+}
+
+// 1-based line ranges of the styles and events sections, headers included
+fn read_section_lines(content: &str) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    let mut is_in_section = false;
+
+    for (idx, line) in content.lines().enumerate() {
+        let line_no = idx + 1;
+        let line = line.trim();
+
+        if line.starts_with('[') {
+            is_in_section = matches!(
+                line.to_ascii_lowercase().as_str(),
+                "[v4+ styles]" | "[v4 styles]" | "[v4++ styles]" | "[v4 styles+]" | "[events]"
+            );
+            if is_in_section {
+                ranges.push(line_no..line_no + 1);
+            }
+        } else if is_in_section && let Some(range) = ranges.last_mut() {
+            range.end = line_no + 1;
         }
+    }
 
-        // add all inline font overrides in the dialogue
-        fonts.extend(
-            FONT_OVRD_REGEX
-                .captures_iter(dialogue.text)
-                .filter_map(|cap| cap.get(1).map(|name| strip_prefix(name.as_str()))),
-        );
-    });
+    ranges
+}
 
-    // extract fonts from used styles
-    fonts.extend(
-        styles
-            .iter()
-            .filter(|style| used_styles.contains(style.name))
-            .map(|style| strip_prefix(style.fontname)),
-    );
+// byte offsets where lines start, to tell the line of a slice of the content
+struct LineIndex<'a> {
+    content: &'a str,
+    starts: Vec<usize>,
+}
 
-    fonts
+impl<'a> LineIndex<'a> {
+    fn new(content: &'a str) -> Self {
+        let starts = [0].into_iter().chain(content.match_indices('\n').map(|(idx, _)| idx + 1));
+        Self { content, starts: starts.collect() }
+    }
+
+    /// 1-based line number, `None` if the text is not a part of the content
+    fn line_of(&self, text: &str) -> Option<usize> {
+        let offset = (text.as_ptr() as usize).checked_sub(self.content.as_ptr() as usize)?;
+        if offset > self.content.len() {
+            return None;
+        }
+        Some(self.starts.partition_point(|&start| start <= offset))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STYLE_FORMAT: &str = "Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, \
+                                OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, \
+                                ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, \
+                                Alignment, MarginL, MarginR, MarginV, Encoding";
+    const EVENT_FORMAT: &str =
+        "Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text";
+
+    /// Styles are `(name, font, bold, italic)`, dialogues are `(style, text)`
+    fn script(info: &str, styles: &[(&str, &str, i32, i32)], dialogues: &[(&str, &str)]) -> String {
+        let mut lines = vec!["[Script Info]".to_owned(), "ScriptType: v4.00+".to_owned()];
+        lines.push(info.to_owned());
+        lines.extend(["".to_owned(), "[V4+ Styles]".to_owned(), STYLE_FORMAT.to_owned()]);
+        for (name, font, bold, italic) in styles {
+            lines.push(format!(
+                "Style: {},{},20,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,{},{},0,0,100,100,0,\
+                 0,1,2,2,2,10,10,10,1",
+                name, font, bold, italic
+            ));
+        }
+        lines.extend(["".to_owned(), "[Events]".to_owned(), EVENT_FORMAT.to_owned()]);
+        for (style, text) in dialogues {
+            lines.push(format!("Dialogue: 0,0:00:00.00,0:00:01.00,{},,0,0,0,,{}", style, text));
+        }
+        lines.join("\n")
+    }
+
+    /// Characters drawn with each font and style, as `name, weight, is_italic: chars`
+    pub fn describe(fonts: UsedFonts) -> Vec<String> {
+        let mut drawn: Vec<_> = fonts
+            .into_iter()
+            .map(|((name, style), chars)| {
+                let chars: String = chars.into_keys().collect();
+                format!("{}, {}, {}: {}", name, style.weight, style.is_italic, chars)
+            })
+            .collect();
+        drawn.sort();
+        drawn
+    }
+
+    fn drawn(content: &str) -> Vec<String> {
+        describe(get_ssa_fonts(Path::new("test.ass"), content))
+    }
+
+    const DEFAULT: (&str, &str, i32, i32) = ("Default", "Arial", 0, 0);
+
+    #[test]
+    fn font_names() {
+        let content = script("", &[DEFAULT], &[
+            ("Default", r"a{\fnTimes New Roman}b{\fn}c"),
+            ("Default", r"{\fn@Gothic}d{\fn Times New Roman \b1}e"),
+        ]);
+        assert_eq!(drawn(&content), [
+            "Arial, 400, false: ac",
+            "Gothic, 400, false: d",
+            "Times New Roman, 400, false: b",
+            "Times New Roman, 700, false: e",
+        ]);
+    }
+
+    #[test]
+    fn weights_and_slants() {
+        let content = script("", &[DEFAULT, ("Bold", "Arial", -1, 1)], &[
+            ("Default", r"{\b1}a{\b0}b{\b900}c{\b}d{\i1}e{\i}f{\blur3\bord2}g"),
+            ("Bold", r"h{\b0\i0}i{\b\i}j{\b-5}k{\b2000}l"),
+        ]);
+        assert_eq!(drawn(&content), [
+            "Arial, 1000, true: l", "Arial, 400, false: bdfgi", "Arial, 400, true: ek",
+            "Arial, 700, false: a", "Arial, 700, true: hj", "Arial, 900, false: c",
+        ]);
+    }
+
+    #[test]
+    fn resets() {
+        let content = script("", &[DEFAULT, ("Sign", "@Gothic", 1, 1)], &[
+            ("Default", r"{\fnX\b1}a{\r}b{\rSign}c{\fn\b\i0}d{\i}e{\r}f{\rNope}g{\rnd}h"),
+            // only `Default` is case-insensitive
+            ("sign", r"{\r*default}i"),
+            ("*Sign", r"{\fnX}j{\r}k"),
+        ]);
+        assert_eq!(drawn(&content), [
+            "Arial, 400, false: bfghi", "Gothic, 700, false: d", "Gothic, 700, true: cek",
+            "X, 700, false: a", "X, 700, true: j",
+        ]);
+    }
+
+    #[test]
+    fn blocks_and_drawings() {
+        let content = script("", &[DEFAULT], &[
+            ("Default", r"a{comment{\b1}b}{\i1"),
+            ("Default", r"{\p1}m 0 0 l 1 1{\p0}c{\t(\fnX\b1)}d"),
+            ("Default", r"{\fnX}{\p2}m 0 0{\p0} "),
+        ]);
+        assert_eq!(drawn(&content), ["Arial, 400, false: acd", r"Arial, 700, false: 1\bi{}"]);
+    }
+
+    #[test]
+    fn line_breaks() {
+        let dialogues = [("Default", r"a\Nb\nc\hd"), ("Default", r"{\q1}e\nf{\q2}g\nh{\q}i\nj")];
+        let content = script("", &[DEFAULT], &dialogues);
+        assert_eq!(drawn(&content), ["Arial, 400, false:  abcdefghij\u{A0}"]);
+        let content = script("WrapStyle: 2", &[DEFAULT], &dialogues);
+        assert_eq!(drawn(&content), ["Arial, 400, false:  abcdefghij\u{A0}"]);
+
+        let content = script("WrapStyle: 2", &[DEFAULT], &[("Default", r"a\nb{\q0}c\nd")]);
+        assert_eq!(drawn(&content), ["Arial, 400, false:  abcd"]);
+        let content = script("WrapStyle: 2", &[DEFAULT], &[("Default", r"a\nb{\q1}c{\q}d\ne")]);
+        assert_eq!(drawn(&content), ["Arial, 400, false: abcde"]);
+    }
+
+    #[test]
+    fn css_weights_and_slants() {
+        for (value, weight) in
+            [("normal", Some(400)), (" BOLD ", Some(700)), ("1", Some(1)), ("1000", Some(1000))]
+        {
+            assert_eq!(parse_weight(value), weight);
+        }
+        for value in ["0", "1001", "-100", "bolder", "700.5", ""] {
+            assert_eq!(parse_weight(value), None);
+        }
+
+        assert_eq!(parse_slant(" Normal "), Some(false));
+        assert_eq!(parse_slant("italic"), Some(true));
+        assert_eq!(parse_slant("OBLIQUE"), Some(true));
+        assert_eq!(parse_slant("oblique 10deg"), None);
+    }
 }
@@ -0,0 +1,51 @@
+// subtitles are not always UTF-8, legacy encodings like GBK, Big5 and Shift-JIS
+// are common in older CJK fansubs
+
+use std::fs::read;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_8};
+
+/// Read a text file, a BOM always decides the encoding, then the forced one,
+/// and valid UTF-8 is taken as is, otherwise the encoding is guessed from the content
+pub fn read_text(path: &Path, forced: Option<&'static Encoding>) -> Result<String> {
+    let bytes = read(path).with_context(|| format!("Error reading file \"{}\"", path.display()))?;
+
+    Ok(decode_text(&bytes, path, forced))
+}
+
+/// Same as `read_text` for text already in memory, `path` is where it comes from
+pub fn decode_text(bytes: &[u8], path: &Path, forced: Option<&'static Encoding>) -> String {
+    let (encoding, bom_len) = match Encoding::for_bom(bytes) {
+        Some((encoding, bom_len)) => (encoding, bom_len),
+        None => match forced {
+            Some(encoding) => (encoding, 0),
+            None if std::str::from_utf8(bytes).is_ok() => (UTF_8, 0),
+            None => {
+                let encoding = guess_encoding(bytes);
+                eprintln!("Guessed encoding {} for \"{}\"", encoding.name(), path.display());
+                (encoding, 0)
+            }
+        },
+    };
+
+    let (text, has_errors) = encoding.decode_without_bom_handling(&bytes[bom_len..]);
+    if has_errors {
+        eprintln!(
+            "Invalid {} text in \"{}\", some characters are replaced",
+            encoding.name(),
+            path.display()
+        );
+    }
+
+    text.into_owned()
+}
+
+fn guess_encoding(bytes: &[u8]) -> &'static Encoding {
+    let mut detector = EncodingDetector::new();
+    detector.feed(bytes, true);
+    // no TLD hint, and UTF-8 is already ruled out
+    detector.guess(None, false)
+}
@@ -0,0 +1,289 @@
+// fonts embedded in the `[Fonts]` section, written the same way as Aegisub,
+// see https://github.com/TypesettingTools/Aegisub/blob/master/libaegisub/include/libaegisub/ass/uuencode.h
+
+use super::EmbeddedFont;
+
+const FONTS_SECTION: &str = "[Fonts]";
+const EVENTS_SECTION: &str = "[Events]";
+const FONT_NAME_PREFIX: &str = "fontname:";
+const KNOWN_SECTIONS: [&str; 7] = [
+    "[Script Info]", "[V4 Styles]", "[V4+ Styles]", "[V4++ Styles]", FONTS_SECTION, "[Graphics]",
+    EVENTS_SECTION,
+];
+// encoded characters per line
+const LINE_LENGTH: usize = 80;
+// 6-bit values are offset by this
+const UU_FIRST: u8 = 33;
+
+/// Fonts in the `[Fonts]` section, the ones that can't be decoded are reported and skipped
+pub fn read_fonts(content: &str) -> Vec<EmbeddedFont> {
+    let mut fonts = Vec::new();
+    // name and encoded data of the font being read
+    let mut current: Option<(&str, String)> = None;
+    let mut in_section = false;
+
+    let mut finish = |current: Option<(&str, String)>| {
+        let Some((name, encoded)) = current else {
+            return;
+        };
+        match uudecode(&encoded) {
+            Some(data) => fonts.push(EmbeddedFont { name: name.to_owned(), data }),
+            None => eprintln!("Error decoding embedded font \"{}\", ignoring", name),
+        }
+    };
+
+    for line in content.lines().map(str::trim) {
+        if is_section_header(line) {
+            in_section = line.eq_ignore_ascii_case(FONTS_SECTION);
+            finish(current.take());
+        } else if !in_section || line.is_empty() {
+            continue;
+        } else if let Some(name) = line.strip_prefix(FONT_NAME_PREFIX) {
+            finish(current.replace((name.trim(), String::new())));
+        } else if let Some((_, encoded)) = &mut current {
+            encoded.push_str(line);
+        }
+    }
+
+    finish(current);
+
+    fonts
+}
+
+/// The script with fonts appended to the `[Fonts]` section, which is created before `[Events]`
+/// if missing, returns the number of fonts added
+pub fn insert_fonts(content: &str, fonts: &[EmbeddedFont]) -> (String, usize) {
+    let newline = if content.contains("\r\n") { "\r\n" } else { "\n" };
+
+    let lines: Vec<&str> = content.lines().collect();
+    let is_section = |line: &&str| is_section_header(line);
+    let fonts_section =
+        lines.iter().position(|line| line.trim().eq_ignore_ascii_case(FONTS_SECTION));
+
+    let existing: Vec<&str> = fonts_section
+        .map(|start| {
+            lines[start + 1..]
+                .iter()
+                .take_while(|line| !is_section(line))
+                .filter_map(|line| line.strip_prefix(FONT_NAME_PREFIX))
+                .map(str::trim)
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let new_fonts: Vec<_> =
+        fonts.iter().filter(|font| !existing.contains(&font.name.as_str())).collect();
+
+    if new_fonts.is_empty() {
+        return (content.to_owned(), 0);
+    }
+
+    let mut entries = Vec::new();
+    for font in &new_fonts {
+        entries.push(format!("{} {}", FONT_NAME_PREFIX, font.name));
+        entries.extend(uuencode(&font.data));
+    }
+
+    let (insert_at, entries) = match fonts_section {
+        Some(start) => {
+            // after the last non-empty line of the section
+            let end = lines[start + 1..]
+                .iter()
+                .position(is_section)
+                .map_or(lines.len(), |len| start + 1 + len);
+            let last =
+                lines[..end].iter().rposition(|line| !line.trim().is_empty()).unwrap_or(start);
+            (last + 1, entries)
+        }
+        None => {
+            let events =
+                lines.iter().position(|line| line.trim().eq_ignore_ascii_case(EVENTS_SECTION));
+            let mut section = vec![FONTS_SECTION.to_owned()];
+            section.append(&mut entries);
+            match events {
+                Some(events) => {
+                    section.push(String::new());
+                    (events, section)
+                }
+                None => {
+                    section.insert(0, String::new());
+                    (lines.len(), section)
+                }
+            }
+        }
+    };
+
+    let mut output: Vec<&str> = lines[..insert_at].to_vec();
+    output.extend(entries.iter().map(String::as_str));
+    output.extend(&lines[insert_at..]);
+
+    let mut content = output.join(newline);
+    content.push_str(newline);
+
+    (content, new_fonts.len())
+}
+
+// encoded lines may start with `[` too, known sections are told by their names,
+// others by characters out of `!`..=`` ` ``, which encoded lines never have
+fn is_section_header(line: &str) -> bool {
+    let line = line.trim();
+    if !line.starts_with('[') || !line.ends_with(']') {
+        return false;
+    }
+
+    KNOWN_SECTIONS.iter().any(|section| line.eq_ignore_ascii_case(section))
+        || line.bytes().any(|c| !(UU_FIRST..UU_FIRST + 64).contains(&c))
+}
+
+// reverse of `uuencode`, `None` for invalid data
+fn uudecode(encoded: &str) -> Option<Vec<u8>> {
+    // a trailing single character can't hold a byte
+    if encoded.len() % 4 == 1 {
+        return None;
+    }
+
+    let mut data = Vec::with_capacity(encoded.len() / 4 * 3 + 2);
+
+    for chunk in encoded.as_bytes().chunks(4) {
+        let mut value = 0u32;
+        for (idx, &c) in chunk.iter().enumerate() {
+            value |= u32::from(c.checked_sub(UU_FIRST).filter(|&c| c < 64)?) << (6 * (3 - idx));
+        }
+        data.extend(&value.to_be_bytes()[1..chunk.len()]);
+    }
+
+    Some(data)
+}
+
+// every 3 bytes are split into four 6-bit values, each offset by 33,
+// and a trailing group of 1 or 2 bytes is written as 2 or 3 characters
+fn uuencode(data: &[u8]) -> Vec<String> {
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+
+    for chunk in data.chunks(3) {
+        let src =
+            [chunk[0], chunk.get(1).copied().unwrap_or(0), chunk.get(2).copied().unwrap_or(0)];
+        let dst = [
+            src[0] >> 2,
+            ((src[0] & 0x3) << 4) | (src[1] >> 4),
+            ((src[1] & 0xF) << 2) | (src[2] >> 6),
+            src[2] & 0x3F,
+        ];
+        encoded.extend(dst[..chunk.len() + 1].iter().map(|&value| char::from(value + UU_FIRST)));
+    }
+
+    // all ASCII
+    encoded
+        .as_bytes()
+        .chunks(LINE_LENGTH)
+        .map(|line| String::from_utf8_lossy(line).into_owned())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn font(name: &str, data: &[u8]) -> EmbeddedFont {
+        EmbeddedFont { name: name.to_owned(), data: data.to_vec() }
+    }
+
+    // the first and the last characters of the first line are `[` and `]`
+    fn bracketed_data() -> Vec<u8> {
+        let mut data: Vec<u8> = (0..=255).collect();
+        data[0] = 0xE8;
+        data[59] = 0x3C;
+        data
+    }
+
+    #[test]
+    fn uuencode_round_trip() {
+        for len in [0, 1, 2, 3, 4, 5, 6, 59, 60, 61, 62, 1000] {
+            let data: Vec<u8> = (0..len).map(|idx| (idx * 7 + len) as u8).collect();
+            let lines = uuencode(&data);
+
+            assert!(lines.iter().all(|line| line.len() <= LINE_LENGTH));
+            assert!(lines[..lines.len().saturating_sub(1)].iter().all(|l| l.len() == LINE_LENGTH));
+            let encoded = lines.concat();
+            assert_eq!(encoded.len(), len / 3 * 4 + [0, 2, 3][len % 3]);
+            assert_eq!(uudecode(&encoded), Some(data));
+        }
+
+        assert_eq!(uuencode(b"Cat"), ["1W&U"]);
+        assert_eq!(uuencode(&[0]), ["!!"]);
+        assert_eq!(uuencode(&[0xFF, 0xFF]), ["``]"]);
+        let first_line = &uuencode(&bracketed_data())[0];
+        assert!(first_line.starts_with('[') && first_line.ends_with(']'));
+    }
+
+    #[test]
+    fn uudecode_malformed() {
+        assert_eq!(uudecode(""), Some(Vec::new()));
+        // a single trailing character can't hold a byte
+        assert_eq!(uudecode("!"), None);
+        assert_eq!(uudecode("1W&U!"), None);
+        // out of `!`..=`` ` ``
+        assert_eq!(uudecode("1W&a"), None);
+        assert_eq!(uudecode("1W U"), None);
+        assert_eq!(uudecode("1W\u{e9}"), None);
+    }
+
+    #[test]
+    fn section_headers() {
+        assert!(is_section_header("[Events]"));
+        assert!(is_section_header(" [V4+ STYLES] "));
+        assert!(is_section_header("[Aegisub Project Garbage]"));
+        assert!(is_section_header("[fonts]"));
+        assert!(!is_section_header("[EVENTS"));
+        assert!(!is_section_header("[ABC]"));
+        assert!(!is_section_header("fontname: a.ttf"));
+    }
+
+    #[test]
+    fn read_inserted_fonts() {
+        let content = "[Script Info]\r\nTitle: test\r\n\r\n[EVENTS]\r\nFormat: Text\r\n";
+        let fonts = [font("a_0.ttf", &bracketed_data()), font("b_0.ttf", b"b")];
+
+        let (inserted, added) = insert_fonts(content, &fonts);
+        assert_eq!(added, 2);
+        assert!(
+            inserted.starts_with(
+                "[Script Info]\r\nTitle: test\r\n\r\n[Fonts]\r\nfontname: a_0.ttf\r\n["
+            )
+        );
+        assert!(inserted.ends_with("\r\n\r\n[EVENTS]\r\nFormat: Text\r\n"));
+
+        let read = read_fonts(&inserted);
+        assert_eq!(read.len(), 2);
+        for (read, font) in read.iter().zip(&fonts) {
+            assert_eq!((&read.name, &read.data), (&font.name, &font.data));
+        }
+
+        // fonts already there are skipped, others go after them
+        let (again, added) =
+            insert_fonts(&inserted, &[font("b_0.ttf", b"b"), font("c_0.ttf", b"c")]);
+        assert_eq!(added, 1);
+        let names: Vec<_> = read_fonts(&again).into_iter().map(|font| font.name).collect();
+        assert_eq!(names, ["a_0.ttf", "b_0.ttf", "c_0.ttf"]);
+
+        let (same, added) = insert_fonts(&again, &[font("a_0.ttf", b"a")]);
+        assert_eq!((same.as_str(), added), (again.as_str(), 0));
+    }
+
+    #[test]
+    fn insert_fonts_without_events() {
+        let (inserted, added) =
+            insert_fonts("[Script Info]\nTitle: test", &[font("a.ttf", b"Cat")]);
+        assert_eq!(added, 1);
+        assert_eq!(inserted, "[Script Info]\nTitle: test\n\n[Fonts]\nfontname: a.ttf\n1W&U\n");
+    }
+
+    #[test]
+    fn read_fonts_in_any_case() {
+        let content = "[FONTS]\nfontname: a.ttf\n1W&U\n\nfontname: broken.ttf\n1W&U!\nfontname: \
+                       b.ttf\n1W\n&U\n[Graphics]\nfontname: c.ttf\n1W&U\n";
+        let fonts = read_fonts(content);
+        let fonts: Vec<_> = fonts.iter().map(|font| (font.name.as_str(), &font.data[..])).collect();
+        assert_eq!(fonts, [("a.ttf", b"Cat".as_slice()), ("b.ttf", b"Cat")]);
+    }
+}
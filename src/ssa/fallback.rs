@@ -0,0 +1,159 @@
+// a forgiving reader for scripts `ass_core` rejects, recovering what it can line by line,
+// like renderers do, and reporting the rest
+
+use std::path::Path;
+
+use super::{DialogueDef, StyleDef};
+
+// used when a section has no (usable) `Format:` line,
+// field positions we care about are the same in SSA and ASS
+const STYLE_FORMAT: [&str; 23] = [
+    "name", "fontname", "fontsize", "primarycolour", "secondarycolour", "outlinecolour",
+    "backcolour", "bold", "italic", "underline", "strikeout", "scalex", "scaley", "spacing",
+    "angle", "borderstyle", "outline", "shadow", "alignment", "marginl", "marginr", "marginv",
+    "encoding",
+];
+const EVENT_FORMAT: [&str; 10] =
+    ["layer", "start", "end", "style", "name", "marginl", "marginr", "marginv", "effect", "text"];
+
+#[derive(Clone, Copy, PartialEq)]
+enum SectionKind {
+    Styles,
+    Events,
+    Other,
+}
+
+pub fn read_script<'a>(path: &Path, content: &'a str) -> (Vec<StyleDef<'a>>, Vec<DialogueDef<'a>>) {
+    let warn = |line: usize, message: &str| {
+        eprintln!("\"{}\" line {}: {}", path.display(), line, message);
+    };
+
+    let mut styles = Vec::new();
+    let mut dialogues = Vec::new();
+
+    let mut section = SectionKind::Other;
+    let mut seen_sections = Vec::new();
+    let mut style_format: Vec<String> = STYLE_FORMAT.map(str::to_owned).to_vec();
+    let mut event_format: Vec<String> = EVENT_FORMAT.map(str::to_owned).to_vec();
+
+    for (idx, line) in content.lines().enumerate() {
+        let line_no = idx + 1;
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with(';') || line.starts_with("!:") {
+            continue;
+        }
+
+        if line.starts_with('[') {
+            let name = line.to_ascii_lowercase();
+            section = match name.as_str() {
+                "[v4+ styles]" | "[v4 styles]" | "[v4 styles+]" => SectionKind::Styles,
+                "[events]" => SectionKind::Events,
+                _ => SectionKind::Other,
+            };
+            if section != SectionKind::Other {
+                if seen_sections.contains(&section) {
+                    warn(line_no, &format!("Duplicated section {}, merged", line));
+                }
+                seen_sections.push(section);
+            }
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once(':') else {
+            if section != SectionKind::Other {
+                warn(line_no, "Not a \"Key: value\" line, ignored");
+            }
+            continue;
+        };
+
+        let key = key.trim().to_ascii_lowercase();
+
+        match (section, key.as_str()) {
+            (SectionKind::Styles, "format") => {
+                if let Some(format) = read_format(value, &["name", "fontname"]) {
+                    style_format = format;
+                } else {
+                    warn(line_no, "Format line lacks Name or Fontname, using the default one");
+                }
+            }
+            (SectionKind::Events, "format") => {
+                if let Some(format) = read_format(value, &["style", "text"]) {
+                    event_format = format;
+                } else {
+                    warn(line_no, "Format line lacks Style or Text, using the default one");
+                }
+            }
+            (_, "style") => {
+                if section != SectionKind::Styles {
+                    warn(line_no, "Style line outside styles section");
+                }
+
+                let fields: Vec<_> = value.split(',').map(str::trim).collect();
+                if fields.len() != style_format.len() {
+                    warn(
+                        line_no,
+                        &format!(
+                            "Style line has {} fields, expected {}",
+                            fields.len(),
+                            style_format.len()
+                        ),
+                    );
+                }
+
+                let field = |name| field(&style_format, &fields, name);
+                let (Some(name), Some(fontname)) = (field("name"), field("fontname")) else {
+                    warn(line_no, "Style line lacks a name or font name, ignored");
+                    continue;
+                };
+
+                styles.push(StyleDef {
+                    name,
+                    fontname,
+                    bold: field("bold").unwrap_or("0"),
+                    italic: field("italic").unwrap_or("0"),
+                });
+            }
+            (_, "dialogue") => {
+                if section != SectionKind::Events {
+                    warn(line_no, "Dialogue line outside events section");
+                }
+
+                // only the last field may contain commas
+                let fields: Vec<_> = value.splitn(event_format.len(), ',').collect();
+
+                let field = |name| field(&event_format, &fields, name);
+                let (Some(style), Some(text)) = (field("style"), field("text")) else {
+                    warn(
+                        line_no,
+                        &format!(
+                            "Dialogue line has {} fields, expected {}, ignored",
+                            fields.len(),
+                            event_format.len()
+                        ),
+                    );
+                    continue;
+                };
+
+                dialogues.push(DialogueDef { style: style.trim(), text, line: line_no });
+            }
+            _ => {}
+        }
+    }
+
+    if !seen_sections.contains(&SectionKind::Styles) {
+        eprintln!("No styles section in \"{}\", fonts of styles are unknown", path.display());
+    }
+
+    (styles, dialogues)
+}
+
+// lowercased field names, `None` if any required field is missing
+fn read_format(value: &str, required: &[&str]) -> Option<Vec<String>> {
+    let format: Vec<_> = value.split(',').map(|field| field.trim().to_ascii_lowercase()).collect();
+    required.iter().all(|name| format.iter().any(|field| field == name)).then_some(format)
+}
+
+fn field<'a>(format: &[String], fields: &[&'a str], name: &str) -> Option<&'a str> {
+    format.iter().position(|field| field == name).and_then(|idx| fields.get(idx).copied())
+}
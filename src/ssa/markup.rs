@@ -0,0 +1,126 @@
+// HTML-like tags in SRT and WebVTT cue text, e.g. `<font face="Arial">`, `<c.yellow>`, `<v Esme>`
+
+pub enum Markup<'a> {
+    /// With character references decoded
+    Text(String),
+    Open {
+        /// In lowercase
+        name: String,
+        /// WebVTT classes, as in `<c.yellow.bg_blue>`
+        classes: Vec<&'a str>,
+        /// Everything after the name, attributes of SRT tags or WebVTT annotations
+        annotation: &'a str,
+    },
+    Close(String),
+}
+
+/// Tags that are not closed are text
+pub fn tokenize(text: &str) -> Vec<Markup<'_>> {
+    let mut tokens = Vec::new();
+    let mut rest = text;
+
+    while !rest.is_empty() {
+        let tag = rest.find('<').and_then(|start| Some((start, start + rest[start..].find('>')?)));
+
+        let Some((start, end)) = tag else {
+            tokens.push(Markup::Text(decode_references(rest)));
+            break;
+        };
+
+        if start > 0 {
+            tokens.push(Markup::Text(decode_references(&rest[..start])));
+        }
+
+        let tag = rest[start + 1..end].trim();
+        rest = &rest[end + 1..];
+
+        if let Some(name) = tag.strip_prefix('/') {
+            tokens.push(Markup::Close(name.trim().to_ascii_lowercase()));
+            continue;
+        }
+
+        // WebVTT timestamps like `<00:01.000>` are not elements
+        if tag.is_empty() || tag.starts_with(|c: char| c.is_ascii_digit()) {
+            continue;
+        }
+
+        let (head, annotation) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+        let mut parts = head.split('.');
+        let name = parts.next().unwrap_or_default().to_ascii_lowercase();
+        let classes = parts.filter(|class| !class.is_empty()).collect();
+
+        tokens.push(Markup::Open { name, classes, annotation: annotation.trim() });
+    }
+
+    tokens
+}
+
+/// Value of an attribute like `face="Arial"`, quoted or not
+pub fn attribute<'a>(attributes: &'a str, name: &str) -> Option<&'a str> {
+    let mut rest = attributes;
+
+    while let Some((key, value)) = rest.split_once('=') {
+        let key = key.trim().rsplit(char::is_whitespace).next().unwrap_or_default();
+        let value = value.trim_start();
+
+        let (value, next) = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => {
+                let value = &value[1..];
+                value.split_once(quote).unwrap_or((value, ""))
+            }
+            _ => value.split_once(char::is_whitespace).unwrap_or((value, "")),
+        };
+
+        if key.eq_ignore_ascii_case(name) {
+            return Some(value);
+        }
+        rest = next;
+    }
+
+    None
+}
+
+// only the references players are known to handle
+fn decode_references(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let reference = rest.find(';').map(|end| &rest[1..end]);
+        let c = reference.and_then(|reference| match reference {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{A0}'),
+            "lrm" => Some('\u{200E}'),
+            "rlm" => Some('\u{200F}'),
+            _ => {
+                let code = reference.strip_prefix('#')?;
+                let code = match code.strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                    None => code.parse().ok()?,
+                };
+                char::from_u32(code)
+            }
+        });
+
+        match (c, reference) {
+            (Some(c), Some(reference)) => {
+                decoded.push(c);
+                rest = &rest[reference.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    decoded.push_str(rest);
+    decoded
+}
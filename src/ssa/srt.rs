@@ -0,0 +1,83 @@
+// SubRip, fonts are only set by `<font face="...">` tags, text outside them is drawn
+// with whatever the player defaults to, so it is not counted
+
+use std::path::Path;
+
+use super::markup::{Markup, attribute, tokenize};
+use super::{FontStyle, UsedFonts, add_text};
+use crate::font::{WEIGHT_BOLD, WEIGHT_REGULAR};
+
+pub fn get_fonts(_path: &Path, content: &str) -> UsedFonts {
+    let mut fonts = UsedFonts::new();
+
+    // tags are closed at the end of cues by players
+    let mut faces: Vec<Option<&str>> = Vec::new();
+    let mut bold = 0;
+    let mut italic = 0;
+    let mut is_text = false;
+
+    for (idx, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            faces.clear();
+            bold = 0;
+            italic = 0;
+            is_text = false;
+            continue;
+        }
+
+        // the index and the timing come before the text
+        if !is_text {
+            is_text = line.contains("-->");
+            continue;
+        }
+
+        for token in tokenize(line) {
+            match token {
+                Markup::Text(text) => {
+                    // the innermost tag with a face
+                    let Some(face) = faces.iter().rev().flatten().next() else {
+                        continue;
+                    };
+                    let style = FontStyle {
+                        weight: if bold > 0 { WEIGHT_BOLD } else { WEIGHT_REGULAR },
+                        is_italic: italic > 0,
+                    };
+                    add_text(&mut fonts, face, style, &strip_overrides(&text), idx + 1);
+                }
+                Markup::Open { name, annotation, .. } => match name.as_str() {
+                    "font" => faces.push(attribute(annotation, "face").map(str::trim)),
+                    "b" => bold += 1,
+                    "i" => italic += 1,
+                    _ => {}
+                },
+                Markup::Close(name) => match name.as_str() {
+                    "font" => {
+                        faces.pop();
+                    }
+                    "b" => bold = 0.max(bold - 1),
+                    "i" => italic = 0.max(italic - 1),
+                    _ => {}
+                },
+            }
+        }
+    }
+
+    fonts
+}
+
+// SSA override blocks like `{\an8}` are understood by most players
+fn strip_overrides(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find("{\\") {
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+        stripped.push_str(&rest[..start]);
+        rest = &rest[start + end + 1..];
+    }
+
+    stripped.push_str(rest);
+    stripped
+}
@@ -0,0 +1,278 @@
+// override tags in dialogue text, only the ones affecting font selection are recognized,
+// see https://github.com/libass/libass/blob/master/libass/ass_parse.c
+
+/// A piece of dialogue text, in order of appearance
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub enum Token<'a> {
+    /// Plain text, escapes like `\N` are kept as is
+    Text(&'a str),
+    Tag(Tag<'a>),
+}
+
+#[cfg_attr(test, derive(Debug, PartialEq))]
+pub enum Tag<'a> {
+    /// `\fn`, empty for the font of the current style
+    FontName(&'a str),
+    /// `\r`, or `\r<style>` to reset to another style
+    Reset(Option<&'a str>),
+    /// `\p`, 0 leaves drawing mode
+    Drawing(u32),
+    /// `\b`, `None` for the value of the current style
+    Bold(Option<i32>),
+    /// `\i`, `None` for the value of the current style
+    Italic(Option<i32>),
+    /// `\q`, `None` for `WrapStyle` of the script
+    WrapStyle(Option<i32>),
+}
+
+pub fn tokenize(text: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut rest = text;
+
+    while !rest.is_empty() {
+        let Some(start) = rest.find('{') else {
+            tokens.push(Token::Text(rest));
+            break;
+        };
+
+        // an unclosed block is plain text
+        let Some(len) = rest[start..].find('}') else {
+            tokens.push(Token::Text(rest));
+            break;
+        };
+
+        if start > 0 {
+            tokens.push(Token::Text(&rest[..start]));
+        }
+
+        tokens.extend(parse_block(&rest[start + 1..start + len]).map(Token::Tag));
+        rest = &rest[start + len + 1..];
+    }
+
+    tokens
+}
+
+// anything between tags is a comment, same as libass
+fn parse_block(block: &str) -> impl Iterator<Item = Tag<'_>> {
+    let mut rest = block;
+
+    std::iter::from_fn(move || {
+        loop {
+            let start = rest.find('\\')?;
+            rest = &rest[start + 1..];
+
+            // font names run until the next tag, they may contain parentheses
+            if let Some(name) = rest.strip_prefix("fn") {
+                let end = name.find('\\').unwrap_or(name.len());
+                rest = &name[end..];
+                return Some(Tag::FontName(name[..end].trim()));
+            }
+
+            let end = tag_end(rest);
+            let tag = rest[..end].trim();
+            rest = &rest[end..];
+
+            // `\rnd` and friends are not resets
+            if let Some(style) = tag.strip_prefix('r')
+                && !style.starts_with("nd")
+            {
+                let style = style.trim();
+                return Some(Tag::Reset(if style.is_empty() { None } else { Some(style) }));
+            }
+
+            // not to be confused with `\blur`, `\bord`, `\iclip`, etc.
+            if let Some(value) = tag.strip_prefix('b')
+                && let Some(value) = parse_toggle(value)
+            {
+                return Some(Tag::Bold(value));
+            }
+
+            if let Some(value) = tag.strip_prefix('i')
+                && let Some(value) = parse_toggle(value)
+            {
+                return Some(Tag::Italic(value));
+            }
+
+            if let Some(value) = tag.strip_prefix('q')
+                && let Some(value) = parse_toggle(value)
+            {
+                return Some(Tag::WrapStyle(value));
+            }
+
+            if let Some(mode) = tag.strip_prefix('p')
+                && !mode.starts_with(|c: char| c.is_ascii_alphabetic())
+            {
+                // like `atoi`, invalid values are 0
+                let digits = mode.trim_start().split(|c: char| !c.is_ascii_digit()).next();
+                return Some(Tag::Drawing(digits.and_then(|d| d.parse().ok()).unwrap_or(0)));
+            }
+        }
+    })
+}
+
+// `Some(None)` for no value, `None` if it's not a number at all
+fn parse_toggle(value: &str) -> Option<Option<i32>> {
+    let digits = value.strip_prefix('-').unwrap_or(value);
+
+    if !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    Some(if digits.is_empty() { None } else { value.parse().ok() })
+}
+
+// arguments in parentheses (e.g. `\t(\fs20)`) belong to the tag
+fn tag_end(tag: &str) -> usize {
+    let mut depth = 0usize;
+
+    for (idx, c) in tag.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            '\\' if depth == 0 => return idx,
+            _ => {}
+        }
+    }
+
+    tag.len()
+}
+
+/// Characters drawn for the text like in libass, line breaks `\N` are dropped,
+/// `\n` is a space unless `is_hard_wrap` (wrap style 2) makes it a line break too,
+/// and `\h` is a no-break space
+pub fn text_chars(text: &str, is_hard_wrap: bool) -> impl Iterator<Item = char> {
+    let mut chars = text.chars().peekable();
+
+    std::iter::from_fn(move || {
+        loop {
+            let c = chars.next()?;
+
+            if c != '\\' {
+                return Some(c);
+            }
+
+            match chars.peek() {
+                Some('N') => {
+                    chars.next();
+                }
+                Some('n') => {
+                    chars.next();
+                    if !is_hard_wrap {
+                        return Some(' ');
+                    }
+                }
+                Some('h') => {
+                    chars.next();
+                    return Some('\u{A0}');
+                }
+                _ => return Some(c),
+            }
+        }
+    })
+}
+
+/// Whether the text would draw any glyph, line breaks and hard spaces don't
+pub fn is_visible(text: &str) -> bool {
+    text_chars(text, true).any(|c| !c.is_whitespace())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Tag::*;
+    use super::Token::Text;
+    use super::*;
+
+    fn tags(text: &str) -> Vec<Tag<'_>> {
+        tokenize(text)
+            .into_iter()
+            .filter_map(|token| match token {
+                Token::Tag(tag) => Some(tag),
+                Text(_) => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn blocks() {
+        assert_eq!(tokenize(""), []);
+        assert_eq!(tokenize("plain"), [Text("plain")]);
+        assert_eq!(tokenize("a{\\b1}b{}c{comment}"), [
+            Text("a"),
+            Token::Tag(Bold(Some(1))),
+            Text("b"),
+            Text("c")
+        ]);
+        // an opening brace inside a block is a part of it
+        assert_eq!(tokenize("a{x{\\b1}b}c"), [Text("a"), Token::Tag(Bold(Some(1))), Text("b}c")]);
+        // unclosed blocks are text
+        assert_eq!(tokenize("a{\\b1}b{\\i1"), [
+            Text("a"),
+            Token::Tag(Bold(Some(1))),
+            Text("b{\\i1")
+        ]);
+        assert_eq!(tokenize("}{"), [Text("}{")]);
+    }
+
+    #[test]
+    fn font_names() {
+        assert_eq!(tags("{\\fnTimes New Roman\\b1}"), [FontName("Times New Roman"), Bold(Some(1))]);
+        assert_eq!(tags("{\\fn Arial (Cyrillic) }"), [FontName("Arial (Cyrillic)")]);
+        assert_eq!(tags("{\\fn}"), [FontName("")]);
+        assert_eq!(tags("{\\fnA\\fn@B}"), [FontName("A"), FontName("@B")]);
+    }
+
+    #[test]
+    fn resets() {
+        assert_eq!(tags("{\\r}"), [Reset(None)]);
+        assert_eq!(tags("{\\r Sign \\b0}"), [Reset(Some("Sign")), Bold(Some(0))]);
+        assert_eq!(tags("{\\rnd2\\rndx3}"), []);
+    }
+
+    #[test]
+    fn toggles() {
+        assert_eq!(tags("{\\b\\b0\\b1\\b700\\b-1}"), [
+            Bold(None),
+            Bold(Some(0)),
+            Bold(Some(1)),
+            Bold(Some(700)),
+            Bold(Some(-1))
+        ]);
+        assert_eq!(tags("{\\i\\i1\\q2\\q}"), [
+            Italic(None),
+            Italic(Some(1)),
+            WrapStyle(Some(2)),
+            WrapStyle(None)
+        ]);
+        // other tags starting with the same letters
+        assert_eq!(tags("{\\blur2\\bord1\\be1\\iclip(0,0,1,1)\\pos(1,2)\\bx}"), []);
+    }
+
+    #[test]
+    fn drawings() {
+        assert_eq!(tags("{\\p1\\p0\\p\\p 2\\px\\p-1\\pbo2}"), [
+            Drawing(1),
+            Drawing(0),
+            Drawing(0),
+            Drawing(2),
+            Drawing(0)
+        ]);
+    }
+
+    #[test]
+    fn comments_and_arguments_of_other_tags() {
+        assert_eq!(tags("{comment\\b1\\i1}comment"), [Bold(Some(1)), Italic(Some(1))]);
+        assert_eq!(tags("{\\t(0,100,\\fnX\\b1)\\i1}"), [Italic(Some(1))]);
+        assert_eq!(tags("{\\t(\\clip(0,0,1,1))\\b1}"), [Bold(Some(1))]);
+    }
+
+    #[test]
+    fn drawn_chars() {
+        let chars = |text, is_hard_wrap| text_chars(text, is_hard_wrap).collect::<String>();
+        assert_eq!(chars("a\\Nb\\nc\\hd\\\\e\\", false), "ab c\u{A0}d\\\\e\\");
+        assert_eq!(chars("a\\Nb\\nc", true), "abc");
+
+        assert!(is_visible("a"));
+        assert!(!is_visible(" \\N\\n\\h "));
+        assert!(is_visible("\\"));
+    }
+}
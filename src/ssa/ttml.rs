@@ -0,0 +1,238 @@
+// TTML and its profiles like IMSC and DFXP, fonts are set by `tts:fontFamily`,
+// on content elements, referred styles and regions, and inherited down the tree
+// https://www.w3.org/TR/ttml2/#styling
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use roxmltree::{Document, NS_XML_URI, Node, ParsingOptions};
+
+use super::{FontStyle, LineIndex, UsedFonts, add_text, first_family, parse_slant, parse_weight};
+use crate::font::WEIGHT_REGULAR;
+
+const GENERIC_FAMILIES: [&str; 8] = [
+    "default", "monospace", "sansSerif", "serif", "monospaceSansSerif", "monospaceSerif",
+    "proportionalSansSerif", "proportionalSerif",
+];
+
+#[derive(Clone, Default)]
+struct Styles {
+    family: Option<String>,
+    weight: Option<u16>,
+    is_italic: Option<bool>,
+}
+
+impl Styles {
+    // values set in `other` win
+    fn apply(&mut self, other: &Self) {
+        if other.family.is_some() {
+            self.family.clone_from(&other.family);
+        }
+        if other.weight.is_some() {
+            self.weight = other.weight;
+        }
+        if other.is_italic.is_some() {
+            self.is_italic = other.is_italic;
+        }
+    }
+}
+
+pub fn get_fonts(path: &Path, content: &str) -> UsedFonts {
+    let options = ParsingOptions { allow_dtd: true, ..ParsingOptions::default() };
+    let doc = match Document::parse_with_options(content, options) {
+        Ok(doc) => doc,
+        Err(err) => {
+            eprintln!("Error parsing \"{}\" ({}), ignoring", path.display(), err);
+            return UsedFonts::new();
+        }
+    };
+
+    let mut styles = HashMap::new();
+    for node in doc.descendants().filter(|node| is_element(*node, "style")) {
+        if let Some(id) = node.attribute((NS_XML_URI, "id")) {
+            let resolved = resolve_style(&doc, id, &mut Vec::new());
+            styles.insert(id, resolved);
+        }
+    }
+
+    let mut regions = HashMap::new();
+    for node in doc.descendants().filter(|node| is_element(*node, "region")) {
+        if let Some(id) = node.attribute((NS_XML_URI, "id")) {
+            let mut region = referred_styles(node, &styles);
+            // styles nested in regions apply to them
+            for child in node.children().filter(|child| is_element(*child, "style")) {
+                region.apply(&inline_styles(child));
+            }
+            region.apply(&inline_styles(node));
+            regions.insert(id, region);
+        }
+    }
+
+    let lines = LineIndex::new(content);
+    let mut fonts = UsedFonts::new();
+
+    if let Some(body) = doc.descendants().find(|node| is_element(*node, "body")) {
+        let context = Context { styles: &styles, regions: &regions, lines: &lines, content };
+        walk(body, Styles::default(), false, &context, &mut fonts);
+    }
+
+    fonts
+}
+
+struct Context<'a> {
+    styles: &'a HashMap<&'a str, Styles>,
+    regions: &'a HashMap<&'a str, Styles>,
+    lines: &'a LineIndex<'a>,
+    content: &'a str,
+}
+
+// text only counts in paragraphs, whitespace between elements is not drawn
+fn walk(
+    node: Node,
+    inherited: Styles,
+    is_in_paragraph: bool,
+    context: &Context,
+    fonts: &mut UsedFonts,
+) {
+    let mut computed = inherited;
+    if let Some(region) = node.attribute("region").and_then(|id| context.regions.get(id)) {
+        computed.apply(region);
+    }
+    computed.apply(&referred_styles(node, context.styles));
+    computed.apply(&inline_styles(node));
+
+    let is_in_paragraph = is_in_paragraph || is_element(node, "p");
+
+    for child in node.children() {
+        if child.is_element() {
+            walk(child, computed.clone(), is_in_paragraph, context, fonts);
+            continue;
+        }
+
+        let (Some(text), Some(family)) = (child.text(), &computed.family) else {
+            continue;
+        };
+        if !is_in_paragraph {
+            continue;
+        }
+
+        let style = FontStyle {
+            weight: computed.weight.unwrap_or(WEIGHT_REGULAR),
+            is_italic: computed.is_italic == Some(true),
+        };
+
+        // references can't contain line breaks, so lines are counted in the decoded text
+        let first_line = context.lines.line_of(&context.content[child.range()]).unwrap_or(1);
+        for (offset, text) in text.split('\n').enumerate() {
+            add_text(fonts, family, style, text, first_line + offset);
+        }
+    }
+}
+
+// `style` attributes refer to styles by their IDs, separated by spaces
+fn referred_styles(node: Node, styles: &HashMap<&str, Styles>) -> Styles {
+    let mut referred = Styles::default();
+
+    for id in node.attribute("style").unwrap_or_default().split_whitespace() {
+        if let Some(style) = styles.get(id) {
+            referred.apply(style);
+        }
+    }
+
+    referred
+}
+
+// styles may refer to other styles, `visiting` breaks reference loops
+fn resolve_style<'a>(doc: &'a Document, id: &'a str, visiting: &mut Vec<&'a str>) -> Styles {
+    let Some(node) = doc
+        .descendants()
+        .find(|node| is_element(*node, "style") && node.attribute((NS_XML_URI, "id")) == Some(id))
+    else {
+        return Styles::default();
+    };
+
+    visiting.push(id);
+
+    let mut resolved = Styles::default();
+    for referred in node.attribute("style").unwrap_or_default().split_whitespace() {
+        if !visiting.contains(&referred) {
+            resolved.apply(&resolve_style(doc, referred, visiting));
+        }
+    }
+    resolved.apply(&inline_styles(node));
+
+    visiting.pop();
+
+    resolved
+}
+
+// `tts:*` attributes of the element itself, any version of the styling namespace
+fn inline_styles(node: Node) -> Styles {
+    let mut styles = Styles::default();
+
+    for attribute in node.attributes() {
+        if !attribute.namespace().is_some_and(|namespace| namespace.ends_with("#styling")) {
+            continue;
+        }
+
+        let value = attribute.value().trim();
+        match attribute.name() {
+            "fontFamily" => styles.family = first_family(value, &GENERIC_FAMILIES),
+            "fontWeight" => styles.weight = parse_weight(value),
+            "fontStyle" => styles.is_italic = parse_slant(value),
+            _ => {}
+        }
+    }
+
+    styles
+}
+
+// elements are matched by their local names, as documents use various namespaces
+fn is_element(node: Node, name: &str) -> bool {
+    node.is_element() && node.tag_name().name() == name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::describe;
+    use super::*;
+
+    const DOCUMENT: &str = r#"<?xml version="1.0"?>
+<tt xmlns="http://www.w3.org/ns/ttml" xmlns:tts="http://www.w3.org/ns/ttml#styling">
+  <head>
+    <styling>
+      <style xml:id="base" tts:fontFamily="'Base Font', sansSerif"/>
+      <style xml:id="heavy" style="base" tts:fontWeight="900"/>
+      <style xml:id="loop" style="loop heavy" tts:fontStyle="italic"/>
+    </styling>
+    <layout>
+      <region xml:id="top" tts:fontFamily="Region Font"/>
+    </layout>
+  </head>
+  <body>
+    <div>
+      <p tts:fontFamily="default">no font</p>
+    </div>
+    <div style="base">
+      <p>a<span tts:fontWeight="bold">b</span></p>
+      <p style="heavy">c<span tts:fontWeight="normal" tts:fontStyle="oblique">d</span></p>
+      <p style="loop">e</p>
+      <p region="top">f<span style="base">g</span></p>
+    </div>
+  </body>
+</tt>
+"#;
+
+    #[test]
+    fn inherited_fonts() {
+        assert_eq!(describe(get_fonts(Path::new("test.ttml"), DOCUMENT)), [
+            "Base Font, 400, false: ag", "Base Font, 400, true: d", "Base Font, 700, false: b",
+            "Base Font, 900, false: c", "Base Font, 900, true: e", "Region Font, 400, false: f",
+        ]);
+    }
+
+    #[test]
+    fn malformed_documents() {
+        assert!(get_fonts(Path::new("test.ttml"), "<tt><body><p>text</body></tt>").is_empty());
+    }
+}
@@ -0,0 +1,329 @@
+// WebVTT, fonts are set by `::cue` rules in `STYLE` blocks
+// https://www.w3.org/TR/webvtt1/#css-extensions
+
+use std::path::Path;
+
+use super::markup::{Markup, tokenize};
+use super::{FontStyle, UsedFonts, add_text, first_family, parse_slant, parse_weight};
+use crate::font::{WEIGHT_BOLD, WEIGHT_REGULAR};
+
+const GENERIC_FAMILIES: [&str; 15] = [
+    "serif", "sans-serif", "monospace", "cursive", "fantasy", "system-ui", "ui-serif",
+    "ui-sans-serif", "ui-monospace", "ui-rounded", "math", "emoji", "fangsong", "inherit",
+    "initial",
+];
+
+// what a rule sets, later rules override earlier ones
+#[derive(Clone, Default)]
+struct Declarations {
+    family: Option<String>,
+    weight: Option<u16>,
+    is_italic: Option<bool>,
+}
+
+/// `::cue` itself, `::cue(#id)` for the whole cue, or the elements in it like `::cue(c.yellow)`
+struct Selector {
+    tag: Option<String>,
+    classes: Vec<String>,
+    id: Option<String>,
+    voice: Option<String>,
+}
+
+impl Selector {
+    fn is_root(&self) -> bool {
+        self.tag.is_none() && self.classes.is_empty() && self.voice.is_none()
+    }
+
+    fn matches_cue(&self, cue_id: Option<&str>) -> bool {
+        self.is_root() && self.id.as_deref().is_none_or(|id| Some(id) == cue_id)
+    }
+
+    fn matches_element(&self, name: &str, classes: &[&str], annotation: &str) -> bool {
+        !self.is_root()
+            && self.id.is_none()
+            && self.tag.as_deref().is_none_or(|tag| tag == name)
+            && self.classes.iter().all(|class| classes.contains(&class.as_str()))
+            && self.voice.as_deref().is_none_or(|voice| name == "v" && voice == annotation)
+    }
+}
+
+#[derive(Clone)]
+struct CueState<'a> {
+    family: Option<&'a str>,
+    weight: u16,
+    is_italic: bool,
+}
+
+impl<'a> CueState<'a> {
+    fn apply(&mut self, declarations: &'a Declarations) {
+        if let Some(family) = &declarations.family {
+            self.family = Some(family);
+        }
+        if let Some(weight) = declarations.weight {
+            self.weight = weight;
+        }
+        if let Some(is_italic) = declarations.is_italic {
+            self.is_italic = is_italic;
+        }
+    }
+}
+
+pub fn get_fonts(_path: &Path, content: &str) -> UsedFonts {
+    let lines: Vec<&str> = content.lines().collect();
+
+    // blocks are separated by blank lines, (line number of the first line, lines)
+    let mut blocks = Vec::new();
+    let mut start = 0;
+    for (idx, line) in lines.iter().enumerate() {
+        if line.trim().is_empty() {
+            if start < idx {
+                blocks.push((start + 1, &lines[start..idx]));
+            }
+            start = idx + 1;
+        }
+    }
+    if start < lines.len() {
+        blocks.push((start + 1, &lines[start..]));
+    }
+
+    let mut rules = Vec::new();
+    for (_, block) in &blocks {
+        if block[0].trim() == "STYLE" {
+            rules.extend(parse_css(&block[1..].join("\n")));
+        }
+    }
+
+    let mut fonts = UsedFonts::new();
+
+    for (first_line, block) in blocks {
+        // the header, comments and regions are skipped along with styles
+        let Some(timing) = block.iter().position(|line| line.contains("-->")) else {
+            continue;
+        };
+        let cue_id = if timing > 0 { Some(block[0].trim()) } else { None };
+
+        let mut root = CueState { family: None, weight: WEIGHT_REGULAR, is_italic: false };
+        for (selector, declarations) in &rules {
+            if selector.matches_cue(cue_id) {
+                root.apply(declarations);
+            }
+        }
+
+        // tags may span lines of a cue
+        let mut states = vec![root];
+
+        for (idx, line) in block.iter().enumerate().skip(timing + 1) {
+            for token in tokenize(line) {
+                match token {
+                    Markup::Text(text) => {
+                        let Some(state) = states.last() else {
+                            continue;
+                        };
+                        if let Some(family) = state.family {
+                            let style =
+                                FontStyle { weight: state.weight, is_italic: state.is_italic };
+                            add_text(&mut fonts, family, style, &text, first_line + idx);
+                        }
+                    }
+                    Markup::Open { name, classes, annotation } => {
+                        let Some(mut state) = states.last().cloned() else {
+                            continue;
+                        };
+
+                        // default styles of user agents
+                        match name.as_str() {
+                            "b" => state.weight = WEIGHT_BOLD,
+                            "i" => state.is_italic = true,
+                            _ => {}
+                        }
+
+                        for (selector, declarations) in &rules {
+                            if selector.matches_element(&name, &classes, annotation) {
+                                state.apply(declarations);
+                            }
+                        }
+
+                        states.push(state);
+                    }
+                    Markup::Close(_) => {
+                        if states.len() > 1 {
+                            states.pop();
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fonts
+}
+
+// rules of `::cue` selectors that set fonts, others are ignored
+fn parse_css(css: &str) -> Vec<(Selector, Declarations)> {
+    let css = strip_comments(css);
+    let mut rules = Vec::new();
+
+    for rule in css.split('}') {
+        let Some((selectors, body)) = rule.split_once('{') else {
+            continue;
+        };
+
+        let declarations = parse_declarations(body);
+        if declarations.family.is_none()
+            && declarations.weight.is_none()
+            && declarations.is_italic.is_none()
+        {
+            continue;
+        }
+
+        for selector in selectors.split(',').filter_map(parse_selector) {
+            rules.push((selector, declarations.clone()));
+        }
+    }
+
+    rules
+}
+
+fn strip_comments(css: &str) -> String {
+    let mut stripped = String::with_capacity(css.len());
+    let mut rest = css;
+
+    while let Some(start) = rest.find("/*") {
+        stripped.push_str(&rest[..start]);
+        rest = rest[start + 2..].split_once("*/").map_or("", |(_, rest)| rest);
+    }
+
+    stripped.push_str(rest);
+    stripped
+}
+
+// compound selectors only, e.g. `::cue`, `::cue(#id)`, `::cue(c.yellow)`, `::cue(v[voice="Esme"])`
+fn parse_selector(selector: &str) -> Option<Selector> {
+    let rest = selector.trim().strip_prefix("::cue")?;
+    let mut parsed = Selector { tag: None, classes: Vec::new(), id: None, voice: None };
+
+    if rest.is_empty() {
+        return Some(parsed);
+    }
+
+    let mut arg = rest.strip_prefix('(')?.strip_suffix(')')?.trim();
+
+    if let Some(start) = arg.find('[') {
+        let attribute = arg[start + 1..].strip_suffix(']')?;
+        let (name, value) = attribute.split_once('=')?;
+        if name.trim() != "voice" {
+            return None;
+        }
+        parsed.voice = Some(value.trim().trim_matches(['"', '\'']).to_owned());
+        arg = &arg[..start];
+    }
+
+    // combinators and pseudo-classes are not supported
+    if arg.contains(|c: char| c.is_whitespace() || matches!(c, ':' | '>' | '+' | '~')) {
+        return None;
+    }
+
+    let mut rest = arg;
+    while !rest.is_empty() {
+        let end = rest
+            .char_indices()
+            .skip(1)
+            .find(|&(_, c)| matches!(c, '.' | '#'))
+            .map_or(rest.len(), |(end, _)| end);
+        let (part, next) = rest.split_at(end);
+
+        if let Some(class) = part.strip_prefix('.') {
+            parsed.classes.push(class.to_owned());
+        } else if let Some(id) = part.strip_prefix('#') {
+            parsed.id = Some(id.to_owned());
+        } else {
+            parsed.tag = Some(part.to_ascii_lowercase());
+        }
+        rest = next;
+    }
+
+    Some(parsed)
+}
+
+fn parse_declarations(body: &str) -> Declarations {
+    let mut declarations = Declarations::default();
+
+    for declaration in body.split(';') {
+        let Some((property, value)) = declaration.split_once(':') else {
+            continue;
+        };
+        let value = value.trim().trim_end_matches("!important").trim();
+
+        match property.trim().to_ascii_lowercase().as_str() {
+            "font-family" => declarations.family = first_family(value, &GENERIC_FAMILIES),
+            "font-weight" => declarations.weight = parse_weight(value),
+            "font-style" => declarations.is_italic = parse_slant(value),
+            "font" => parse_shorthand(value, &mut declarations),
+            _ => {}
+        }
+    }
+
+    declarations
+}
+
+// `font: italic bold 1em/1.2 "Foo", sans-serif`, the family comes after the size
+fn parse_shorthand(value: &str, declarations: &mut Declarations) {
+    let mut rest = value;
+
+    while let Some((word, next)) = rest.split_once(char::is_whitespace) {
+        let is_size = word.starts_with(|c: char| c.is_ascii_digit() || c == '.')
+            && !word.chars().all(|c| c.is_ascii_digit());
+        rest = next.trim_start();
+
+        if is_size {
+            declarations.family = first_family(rest, &GENERIC_FAMILIES);
+            return;
+        }
+
+        if let Some(weight) = parse_weight(word) {
+            declarations.weight = Some(weight);
+        } else if let Some(is_italic) = parse_slant(word) {
+            declarations.is_italic = Some(is_italic);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::describe;
+    use super::*;
+
+    const CUES: &str = r#"WEBVTT
+
+STYLE
+::cue { font-family: "Noto Sans", sans-serif; }
+::cue(b) { font-weight: 900 }
+::cue(.title) { font: italic bold 1em/1.2 Title Font, serif }
+::cue(#intro) { font-family: Intro; font-weight: normal !important }
+::cue(v[voice="Esme"]) { font-style: oblique }
+/* ::cue { font-family: Commented } */
+::cue(c > b), ::cue(:past) { font-family: Unsupported }
+
+intro
+00:00.000 --> 00:01.000
+a<b>b</b>
+
+00:01.000 --> 00:02.000
+c<b>d</b><i>e</i><c.title>f</c>
+<v Esme>g</v>
+"#;
+
+    #[test]
+    fn cue_fonts() {
+        assert_eq!(describe(get_fonts(Path::new("test.vtt"), CUES)), [
+            "Intro, 400, false: a", "Intro, 900, false: b", "Noto Sans, 400, false: c",
+            "Noto Sans, 400, true: eg", "Noto Sans, 900, false: d", "Title Font, 700, true: f",
+        ]);
+    }
+
+    #[test]
+    fn without_styles() {
+        let content = "WEBVTT\n\n00:00.000 --> 00:01.000\n<b>text</b>\n";
+        assert!(get_fonts(Path::new("test.vtt"), content).is_empty());
+    }
+}
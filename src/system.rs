@@ -4,9 +4,14 @@ mod linux;
 #[cfg(target_os = "windows")]
 mod windows;
 
+use std::fs::write;
 use std::path::{Path, PathBuf};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
+use tempfile::{TempDir, tempdir};
+
+use crate::font::decode_web_font_file;
+use crate::utils::collapse_whitespace;
 
 #[cfg(target_os = "linux")]
 type FinderImpl = self::linux::FontconfigFinder;
@@ -25,8 +30,20 @@ impl Finder {
         return Ok(Self(self::windows::Finder));
     }
 
+    /// Backends receive names with whitespace normalized, in their original case,
+    /// as styles in them are told apart by case-sensitive comparisons
     pub fn get_font_file(&self, name: impl AsRef<str>) -> Result<Option<PathBuf>> {
-        self.0.get_font_file(name)
+        self.0.get_font_file(collapse_whitespace(name.as_ref()))
+    }
+
+    /// The file the system would pick for the name drawn in the style
+    pub fn get_styled_font_file(
+        &self,
+        name: impl AsRef<str>,
+        weight: u16,
+        is_italic: bool,
+    ) -> Result<Option<PathBuf>> {
+        self.0.get_styled_font_file(collapse_whitespace(name.as_ref()), weight, is_italic)
     }
 }
 
@@ -36,32 +53,96 @@ type LoaderImpl = self::linux::FontconfigLoader;
 #[cfg(target_os = "windows")]
 type LoaderImpl = self::windows::Loader;
 
-pub struct Loader(Option<LoaderImpl>);
+pub struct Loader {
+    inner: Option<LoaderImpl>,
+    /// Holds files that can't be loaded in place, e.g. converted web fonts,
+    /// created on first use and removed after fonts are unloaded
+    temp_dir: Option<TempDir>,
+    temp_count: usize,
+}
 
 impl Loader {
     pub fn new() -> Result<Self> {
         #[cfg(target_os = "linux")]
-        return Ok(Self(Some(self::linux::FontconfigLoader::new()?)));
+        let inner = self::linux::FontconfigLoader::new()?;
 
         #[cfg(target_os = "windows")]
-        return Ok(Self(Some(self::windows::Loader::new())));
+        let inner = self::windows::Loader::new();
+
+        Ok(Self { inner: Some(inner), temp_dir: None, temp_count: 0 })
     }
 
-    pub fn load(&mut self, files: impl IntoIterator<Item = impl AsRef<Path>>) -> Result<()> {
+    /// Web fonts are converted to plain font files first,
+    /// as system font APIs don't accept them, ones that can't be are skipped,
+    /// returns the number of files loaded
+    pub fn load(&mut self, files: impl IntoIterator<Item = impl AsRef<Path>>) -> Result<usize> {
+        let mut loadable = Vec::new();
+
+        for file in files {
+            let file = file.as_ref();
+            let decoded = match decode_web_font_file(file) {
+                Ok(decoded) => decoded,
+                Err(err) => {
+                    eprintln!("{:#}, skipping", err);
+                    continue;
+                }
+            };
+
+            match decoded {
+                Some((data, ext)) => {
+                    #[allow(clippy::unwrap_used, reason = "explicit panic as caller fault")]
+                    let name = Path::new(file.file_name().unwrap()).with_extension(ext);
+                    loadable.push(self.write_temp(&name, &data)?);
+                }
+                None => loadable.push(file.to_owned()),
+            }
+        }
+
+        let count = loadable.len();
+
         #[allow(clippy::unwrap_used, reason = "guaranteed `Some`")]
-        self.0.as_mut().unwrap().load(files)
+        self.inner.as_mut().unwrap().load(loadable)?;
+
+        Ok(count)
+    }
+
+    /// Write a file to the temporary directory, returns its path,
+    /// names are prefixed with a counter so they never collide
+    pub fn write_temp(&mut self, name: &Path, data: &[u8]) -> Result<PathBuf> {
+        let temp_dir = match &mut self.temp_dir {
+            Some(temp_dir) => temp_dir,
+            None => self.temp_dir.insert(tempdir()?),
+        };
+
+        self.temp_count += 1;
+        let path = temp_dir.path().join(format!("{}_{}", self.temp_count, name.display()));
+
+        write(&path, data).with_context(|| format!("Error writing file \"{}\"", path.display()))?;
+
+        Ok(path)
     }
 }
 
 impl Drop for Loader {
     fn drop(&mut self) {
+        // fonts must be unloaded before the temporary directory is removed with the fields
         #[allow(clippy::unwrap_used, reason = "guaranteed `Some`")]
-        self.0.take().unwrap().unload_all();
+        self.inner.take().unwrap().unload_all();
     }
 }
 
 trait FindFont {
     fn get_font_file(&self, name: impl AsRef<str>) -> Result<Option<PathBuf>>;
+
+    /// Backends that can't tell styles apart return the file for the name
+    fn get_styled_font_file(
+        &self,
+        name: impl AsRef<str>,
+        _weight: u16,
+        _is_italic: bool,
+    ) -> Result<Option<PathBuf>> {
+        self.get_font_file(name)
+    }
 }
 
 trait LoadFontFiles {
@@ -1,4 +1,4 @@
-use std::ffi::{CStr, CString, OsStr};
+use std::ffi::{CStr, CString, OsStr, c_int};
 use std::fs::{remove_dir_all, remove_file};
 use std::os::unix::ffi::OsStrExt;
 use std::os::unix::fs::symlink;
@@ -6,16 +6,21 @@ use std::path::{Path, PathBuf};
 use std::ptr;
 
 use anyhow::{Context, Result, bail, ensure};
-use fontconfig_sys::constants::{FC_FAMILY, FC_FILE, FC_STYLE};
+use fontconfig_sys::constants::{
+    FC_FAMILY, FC_FILE, FC_SLANT, FC_SLANT_ITALIC, FC_SLANT_ROMAN, FC_STYLE, FC_WEIGHT,
+    FC_WEIGHT_BLACK, FC_WEIGHT_BOLD, FC_WEIGHT_BOOK, FC_WEIGHT_DEMIBOLD, FC_WEIGHT_EXTRABLACK,
+    FC_WEIGHT_EXTRABOLD, FC_WEIGHT_EXTRALIGHT, FC_WEIGHT_LIGHT, FC_WEIGHT_MEDIUM,
+    FC_WEIGHT_REGULAR, FC_WEIGHT_THIN,
+};
 use fontconfig_sys::{
     FcConfigBuildFonts, FcConfigSubstitute, FcDefaultSubstitute, FcDirCacheRead, FcFontMatch,
-    FcMatchPattern, FcPattern, FcPatternAddString, FcPatternCreate, FcPatternDestroy,
-    FcPatternGetString, FcResultMatch,
+    FcMatchPattern, FcPattern, FcPatternAddInteger, FcPatternAddString, FcPatternCreate,
+    FcPatternDestroy, FcPatternGetString, FcResultMatch,
 };
 use tempfile::{TempDir, tempdir};
 
 use super::{FindFont, LoadFontFiles};
-use crate::utils::parse_style;
+use crate::utils::{normalize_font_name, parse_style};
 
 pub struct FontconfigFinder;
 
@@ -30,58 +35,112 @@ pub struct FontconfigFinder;
 // but it'll return the opposite, since no actual font would have the name.
 impl FindFont for FontconfigFinder {
     fn get_font_file(&self, name: impl AsRef<str>) -> Result<Option<PathBuf>> {
-        let (family, style) = parse_style(name.as_ref());
+        match_font(name.as_ref(), None)
+    }
 
-        unsafe {
-            // create the pattern
-            let pattern = FcPatternPtr(FcPatternCreate());
+    fn get_styled_font_file(
+        &self,
+        name: impl AsRef<str>,
+        weight: u16,
+        is_italic: bool,
+    ) -> Result<Option<PathBuf>> {
+        match_font(name.as_ref(), Some((weight, is_italic)))
+    }
+}
 
-            ensure!(!pattern.0.is_null(), "FcPatternCreate returned null pointer");
+// a style in the name still takes precedence over the weight and slant,
+// as fontconfig ranks the style field higher
+fn match_font(name: &str, weight_slant: Option<(u16, bool)>) -> Result<Option<PathBuf>> {
+    let (family, style) = parse_style(name);
+
+    unsafe {
+        // create the pattern
+        let pattern = FcPatternPtr(FcPatternCreate());
+
+        ensure!(!pattern.0.is_null(), "FcPatternCreate returned null pointer");
+
+        // add family name and style to the pattern
+        if FcPatternAddString(
+            pattern.0,
+            FC_FAMILY.as_ptr(),
+            CString::new(family)?.as_ptr() as *const u8,
+        ) == 0
+        {
+            bail!("FcPatternAddString failed");
+        }
 
-            // add family name and style to the pattern
-            if FcPatternAddString(
-                pattern.0,
-                FC_FAMILY.as_ptr(),
-                CString::new(family)?.as_ptr() as *const u8,
-            ) == 0
-            {
-                bail!("FcPatternAddString failed");
-            }
+        // the default style would outrank the weight and slant
+        let has_style = weight_slant.is_none() || !style.eq_ignore_ascii_case("Regular");
 
-            if FcPatternAddString(
+        if has_style
+            && FcPatternAddString(
                 pattern.0,
                 FC_STYLE.as_ptr(),
                 CString::new(style)?.as_ptr() as *const u8,
             ) == 0
+        {
+            bail!("FcPatternAddString failed");
+        }
+
+        if let Some((weight, is_italic)) = weight_slant {
+            let slant = if is_italic { FC_SLANT_ITALIC } else { FC_SLANT_ROMAN };
+            if FcPatternAddInteger(pattern.0, FC_WEIGHT.as_ptr(), fc_weight(weight)) == 0
+                || FcPatternAddInteger(pattern.0, FC_SLANT.as_ptr(), slant) == 0
             {
-                bail!("FcPatternAddString failed");
+                bail!("FcPatternAddInteger failed");
             }
+        }
 
-            // perform substitutions
-            if FcConfigSubstitute(ptr::null_mut(), pattern.0, FcMatchPattern) == 0 {
-                bail!("FcConfigSubstitute failed");
-            };
-            FcDefaultSubstitute(pattern.0);
+        // perform substitutions
+        if FcConfigSubstitute(ptr::null_mut(), pattern.0, FcMatchPattern) == 0 {
+            bail!("FcConfigSubstitute failed");
+        };
+        FcDefaultSubstitute(pattern.0);
 
-            // match the pattern, basically equivalent to `fc-match`
-            let font_match = FcPatternPtr(FcFontMatch(ptr::null_mut(), pattern.0, &mut 0));
+        // match the pattern, basically equivalent to `fc-match`
+        let font_match = FcPatternPtr(FcFontMatch(ptr::null_mut(), pattern.0, &mut 0));
 
-            ensure!(!font_match.0.is_null(), "FcFontMatch returned null pointer");
+        ensure!(!font_match.0.is_null(), "FcFontMatch returned null pointer");
 
-            // check all family names of the returned best match
-            let is_exact = families_in_pattern(&font_match).contains(&family.to_ascii_lowercase());
+        // check all family names of the returned best match
+        let is_exact = families_in_pattern(&font_match).contains(&normalize_font_name(family));
 
-            if !is_exact {
-                return Ok(None);
-            }
+        if !is_exact {
+            return Ok(None);
+        }
 
-            let path = file_in_pattern(&font_match)?;
+        let path = file_in_pattern(&font_match)?;
 
-            Ok(Some(path))
-        }
+        Ok(Some(path))
     }
 }
 
+// OS/2 weights to fontconfig ones, interpolated like `FcWeightFromOpenType`
+fn fc_weight(weight: u16) -> c_int {
+    const MAP: [(u16, c_int); 11] = [
+        (100, FC_WEIGHT_THIN),
+        (200, FC_WEIGHT_EXTRALIGHT),
+        (300, FC_WEIGHT_LIGHT),
+        (380, FC_WEIGHT_BOOK),
+        (400, FC_WEIGHT_REGULAR),
+        (500, FC_WEIGHT_MEDIUM),
+        (600, FC_WEIGHT_DEMIBOLD),
+        (700, FC_WEIGHT_BOLD),
+        (800, FC_WEIGHT_EXTRABOLD),
+        (900, FC_WEIGHT_BLACK),
+        (1000, FC_WEIGHT_EXTRABLACK),
+    ];
+
+    let weight = weight.clamp(100, 1000);
+    let upper = MAP.iter().position(|&(ot, _)| ot >= weight).unwrap_or(MAP.len() - 1);
+    let (ot_hi, fc_hi) = MAP[upper];
+    let Some(&(ot_lo, fc_lo)) = upper.checked_sub(1).map(|idx| &MAP[idx]) else {
+        return fc_hi;
+    };
+
+    fc_lo + (fc_hi - fc_lo) * c_int::from(weight - ot_lo) / c_int::from(ot_hi - ot_lo)
+}
+
 pub struct FontconfigLoader {
     _tmpdir: TempDir,
     link: PathBuf,
@@ -190,9 +249,9 @@ unsafe fn families_in_pattern(pattern: &FcPatternPtr) -> Vec<String> {
             continue;
         }
 
-        let name = unsafe { CStr::from_ptr(match_res_ptr as *const i8) }
-            .to_string_lossy()
-            .to_ascii_lowercase();
+        let name = normalize_font_name(
+            &unsafe { CStr::from_ptr(match_res_ptr as *const i8) }.to_string_lossy(),
+        );
 
         families.push(name);
     }
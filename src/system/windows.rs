@@ -28,8 +28,11 @@ impl FindFont for Finder {
         }
 
         let (family, style) = parse_style(name.as_ref());
-        let face_name =
-            if style == "Regular" { family.to_owned() } else { family.to_owned() + " " + style };
+        let face_name = if style.eq_ignore_ascii_case("Regular") {
+            family.to_owned()
+        } else {
+            family.to_owned() + " " + style
+        };
         let mut name_utf16 = face_name.encode_utf16();
         let name_utf16_arr = std::array::from_fn(|_| name_utf16.next().unwrap_or(0));
 
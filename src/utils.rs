@@ -1,6 +1,7 @@
 use std::collections::HashSet;
-use std::fs::read_dir;
-use std::path::{Path, PathBuf};
+use std::fs::{File, read_dir};
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
 use std::sync::LazyLock;
 
 pub fn walk_dir(
@@ -63,6 +64,67 @@ pub fn get_cache_path_fallback(path: Option<&Path>) -> PathBuf {
     }
 }
 
+/// Lexically resolve `.` and `..` components
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    normalized.push(component);
+                }
+            }
+            _ => normalized.push(component),
+        }
+    }
+
+    normalized
+}
+
+/// Both paths should be absolute and normalized,
+/// returns `None` if they don't share the same root
+pub fn relative_path(path: &Path, base: &Path) -> Option<PathBuf> {
+    let mut path_components = path.components().peekable();
+    let mut base_components = base.components().peekable();
+
+    // prefix (Windows drive) and root must match
+    while let (Some(a), Some(b)) = (path_components.peek(), base_components.peek()) {
+        if a != b {
+            break;
+        }
+        path_components.next();
+        base_components.next();
+    }
+
+    if path_components
+        .peek()
+        .is_some_and(|c| matches!(c, Component::Prefix(_) | Component::RootDir))
+        || base_components
+            .peek()
+            .is_some_and(|c| matches!(c, Component::Prefix(_) | Component::RootDir))
+    {
+        return None;
+    }
+
+    let mut relative: PathBuf = base_components.map(|_| Component::ParentDir).collect();
+    relative.extend(path_components);
+
+    Some(relative)
+}
+
+/// Font names are compared case-insensitively, ignoring leading,
+/// trailing and repeated whitespace, like libass and GDI do
+pub fn normalize_font_name(name: &str) -> String {
+    collapse_whitespace(name).to_lowercase()
+}
+
+/// Leading and trailing whitespace dropped, runs of it replaced by a single space
+pub fn collapse_whitespace(name: &str) -> String {
+    name.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
 pub fn get_font_list_path(path: Option<&Path>) -> PathBuf {
     const DEFAULT_LOCATION: &str = "./fonts.txt";
 
@@ -102,19 +164,45 @@ pub fn parse_style(name: &str) -> (&str, &str) {
     }
 }
 
+/// Check the signature instead of the extension,
+/// since fonts in the wild are often misnamed or lack one
 pub fn is_font(path: &Path) -> bool {
-    ext_endswith(path, &["ttf", "otf", "ttc"])
+    if !path.is_file() {
+        return false;
+    }
+
+    let mut signature = [0; 4];
+    let Ok(mut file) = File::open(path) else {
+        return false;
+    };
+
+    file.read_exact(&mut signature).is_ok() && is_font_data(&signature)
+}
+
+pub fn is_font_data(data: &[u8]) -> bool {
+    const SIGNATURES: [&[u8; 4]; 6] = [b"\0\x01\0\0", b"OTTO", b"true", b"ttcf", b"wOFF", b"wOF2"];
+
+    data.first_chunk().is_some_and(|signature| SIGNATURES.contains(&signature))
 }
 
 pub fn is_ssa(path: &Path) -> bool {
     ext_endswith(path, &["ssa", "ass"])
 }
 
-fn ext_endswith(path: &Path, extensions: &[impl AsRef<str>]) -> bool {
-    if !path.is_file() {
-        return false;
-    }
+pub fn is_matroska(path: &Path) -> bool {
+    ext_endswith(path, &["mkv", "mka", "mks"])
+}
+
+pub fn is_zip(path: &Path) -> bool {
+    ext_endswith(path, &["zip"])
+}
+
+pub fn ext_endswith(path: &Path, extensions: &[impl AsRef<str>]) -> bool {
+    path.is_file() && has_extension(path, extensions)
+}
 
+/// Same as `ext_endswith` without checking the file system, e.g. for members of archives
+pub fn has_extension(path: &Path, extensions: &[impl AsRef<str>]) -> bool {
     let Some(ext) = path.extension() else {
         return false;
     };